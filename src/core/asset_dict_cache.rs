@@ -0,0 +1,106 @@
+use std::{
+    any::{Any, TypeId},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicU64, AtomicUsize},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use fnv::FnvHashMap;
+
+// Parsed asset dicts are small but reloaded often (replaying stories, re-entering lives...)
+const CAPACITY: usize = 64;
+
+struct Entry {
+    value: Arc<dyn Any + Send + Sync>,
+    // Entries are revalidated against the file's mtime so that edits are still picked up
+    mtime: SystemTime,
+    last_used: u64,
+}
+
+/// Bounded LRU cache of parsed asset dicts, keyed by file path and dict type.
+#[derive(Default)]
+pub struct AssetDictCache {
+    entries: Mutex<FnvHashMap<(PathBuf, TypeId), Entry>>,
+    use_counter: AtomicU64,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[derive(Clone, Copy)]
+pub struct Stats {
+    pub hits: usize,
+    pub misses: usize,
+    pub len: usize,
+}
+
+impl AssetDictCache {
+    pub fn get_or_load<T: Send + Sync + 'static>(
+        &self,
+        path: &Path,
+        load: impl FnOnce() -> Option<T>,
+    ) -> Option<Arc<T>> {
+        // Most assets don't have a dict, don't bother caching or counting those
+        let Ok(mtime) = fs::metadata(path).and_then(|m| m.modified()) else {
+            return load().map(Arc::new);
+        };
+
+        let key = (path.to_owned(), TypeId::of::<T>());
+        let last_used = self.use_counter.fetch_add(1, atomic::Ordering::Relaxed);
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(entry) = entries.get_mut(&key).filter(|e| e.mtime == mtime) {
+                if let Ok(value) = entry.value.clone().downcast::<T>() {
+                    entry.last_used = last_used;
+                    self.hits.fetch_add(1, atomic::Ordering::Relaxed);
+                    return Some(value);
+                }
+            }
+        }
+
+        self.misses.fetch_add(1, atomic::Ordering::Relaxed);
+        let value = Arc::new(load()?);
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= CAPACITY && !entries.contains_key(&key) {
+            let lru_key = entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(lru_key) = lru_key {
+                entries.remove(&lru_key);
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                value: value.clone(),
+                mtime,
+                last_used,
+            },
+        );
+
+        Some(value)
+    }
+
+    /// Paths of the cached dicts.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let entries = self.entries.lock().unwrap();
+        let mut paths: Vec<PathBuf> = entries.keys().map(|(path, _)| path.clone()).collect();
+        // The same file could be cached as multiple types
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            hits: self.hits.load(atomic::Ordering::Relaxed),
+            misses: self.misses.load(atomic::Ordering::Relaxed),
+            len: self.entries.lock().unwrap().len(),
+        }
+    }
+}
@@ -0,0 +1,532 @@
+/*
+    Built-in CLDR plural rules (Unicode CLDR, plurals.xml / ordinals.xml).
+
+    Selected in the localized data config with "plural_form": "cldr:ru" or
+    "ordinal_form": "cldr:en". The plural form index is the position of the
+    category in this order: zero, one, two, few, many, other; only counting the
+    categories that the language actually uses. For example, Russian uses
+    one, few, many and other, so $(plural n 'one' 'few' 'many' 'other').
+
+    Rules are stored in CLDR syntax (without samples) and parsed on load.
+    The compact decimal exponent operands (e, c) are always 0.
+*/
+use super::Error;
+
+// Cardinal rules, categories in CLDR order. Languages without an entry only use "other".
+static CARDINAL_RULES: &[(&[&str], &[(&str, &str)])] = &[
+    (&["ja", "zh", "ko", "vi", "th", "id", "ms", "my", "lo", "km", "yue", "jv", "su", "yo", "ig", "bo", "dz"], &[]),
+    (&["en", "de", "nl", "sv", "fi", "et", "gl", "sw", "ur", "fy", "ia", "io", "ji", "yi", "lij", "sc", "scn"], &[
+        ("one", "i = 1 and v = 0"),
+    ]),
+    (&["it", "ca", "vec"], &[
+        ("one", "i = 1 and v = 0"),
+        ("many", "e = 0 and i != 0 and i % 1000000 = 0 and v = 0 or e != 0..5"),
+    ]),
+    (&["es"], &[
+        ("one", "n = 1"),
+        ("many", "e = 0 and i != 0 and i % 1000000 = 0 and v = 0 or e != 0..5"),
+    ]),
+    (&["fr"], &[
+        ("one", "i = 0,1"),
+        ("many", "e = 0 and i != 0 and i % 1000000 = 0 and v = 0 or e != 0..5"),
+    ]),
+    (&["pt"], &[
+        ("one", "i = 0..1"),
+        ("many", "e = 0 and i != 0 and i % 1000000 = 0 and v = 0 or e != 0..5"),
+    ]),
+    (&["pt-pt"], &[
+        ("one", "i = 1 and v = 0"),
+        ("many", "e = 0 and i != 0 and i % 1000000 = 0 and v = 0 or e != 0..5"),
+    ]),
+    (&["da"], &[
+        ("one", "n = 1 or t != 0 and i = 0,1"),
+    ]),
+    (&["is"], &[
+        ("one", "t = 0 and i % 10 = 1 and i % 100 != 11 or t % 10 = 1 and t % 100 != 11"),
+    ]),
+    (&["nb", "nn", "no", "el", "hu", "tr", "bg", "az", "ka", "kk", "ky", "mn", "sq", "uz", "ta", "te", "ml", "ne", "eu", "af", "ps", "so"], &[
+        ("one", "n = 1"),
+    ]),
+    (&["hi", "bn", "fa", "gu", "kn", "zu", "am", "as"], &[
+        ("one", "i = 0 or n = 1"),
+    ]),
+    (&["fil", "tl"], &[
+        ("one", "v = 0 and i = 1,2,3 or v = 0 and i % 10 != 4,6,9 or v != 0 and f % 10 != 4,6,9"),
+    ]),
+    (&["ru", "uk"], &[
+        ("one", "v = 0 and i % 10 = 1 and i % 100 != 11"),
+        ("few", "v = 0 and i % 10 = 2..4 and i % 100 != 12..14"),
+        ("many", "v = 0 and i % 10 = 0 or v = 0 and i % 10 = 5..9 or v = 0 and i % 100 = 11..14"),
+    ]),
+    (&["be"], &[
+        ("one", "n % 10 = 1 and n % 100 != 11"),
+        ("few", "n % 10 = 2..4 and n % 100 != 12..14"),
+        ("many", "n % 10 = 0 or n % 10 = 5..9 or n % 100 = 11..14"),
+    ]),
+    (&["pl"], &[
+        ("one", "i = 1 and v = 0"),
+        ("few", "v = 0 and i % 10 = 2..4 and i % 100 != 12..14"),
+        ("many", "v = 0 and i != 1 and i % 10 = 0..1 or v = 0 and i % 10 = 5..9 or v = 0 and i % 100 = 12..14"),
+    ]),
+    (&["cs", "sk"], &[
+        ("one", "i = 1 and v = 0"),
+        ("few", "i = 2..4 and v = 0"),
+        ("many", "v != 0"),
+    ]),
+    (&["hr", "sr", "bs", "sh"], &[
+        ("one", "v = 0 and i % 10 = 1 and i % 100 != 11 or f % 10 = 1 and f % 100 != 11"),
+        ("few", "v = 0 and i % 10 = 2..4 and i % 100 != 12..14 or f % 10 = 2..4 and f % 100 != 12..14"),
+    ]),
+    (&["mk"], &[
+        ("one", "v = 0 and i % 10 = 1 and i % 100 != 11 or f % 10 = 1 and f % 100 != 11"),
+    ]),
+    (&["sl"], &[
+        ("one", "v = 0 and i % 100 = 1"),
+        ("two", "v = 0 and i % 100 = 2"),
+        ("few", "v = 0 and i % 100 = 3..4 or v != 0"),
+    ]),
+    (&["lt"], &[
+        ("one", "n % 10 = 1 and n % 100 != 11..19"),
+        ("few", "n % 10 = 2..9 and n % 100 != 11..19"),
+        ("many", "f != 0"),
+    ]),
+    (&["lv"], &[
+        ("zero", "n % 10 = 0 or n % 100 = 11..19 or v = 2 and f % 100 = 11..19"),
+        ("one", "n % 10 = 1 and n % 100 != 11 or v = 2 and f % 10 = 1 and f % 100 != 11 or v != 2 and f % 10 = 1"),
+    ]),
+    (&["ro", "mo"], &[
+        ("one", "i = 1 and v = 0"),
+        ("few", "v != 0 or n = 0 or n != 1 and n % 100 = 1..19"),
+    ]),
+    (&["ar", "ars"], &[
+        ("zero", "n = 0"),
+        ("one", "n = 1"),
+        ("two", "n = 2"),
+        ("few", "n % 100 = 3..10"),
+        ("many", "n % 100 = 11..99"),
+    ]),
+    (&["he", "iw"], &[
+        ("one", "i = 1 and v = 0 or i = 0 and v != 0"),
+        ("two", "i = 2 and v = 0"),
+    ]),
+    (&["ga"], &[
+        ("one", "n = 1"),
+        ("two", "n = 2"),
+        ("few", "n = 3..6"),
+        ("many", "n = 7..10"),
+    ]),
+    (&["cy"], &[
+        ("zero", "n = 0"),
+        ("one", "n = 1"),
+        ("two", "n = 2"),
+        ("few", "n = 3"),
+        ("many", "n = 6"),
+    ]),
+];
+
+// Ordinal rules, categories in CLDR order. Languages without an entry only use "other".
+static ORDINAL_RULES: &[(&[&str], &[(&str, &str)])] = &[
+    (&["ja", "zh", "ko", "th", "id", "de", "es", "pt", "nl", "ru", "pl", "cs", "sk", "fi", "da", "nb", "no",
+        "tr", "el", "ar", "he", "hr", "sr", "bs", "sl", "lt", "lv", "et", "is", "be", "bg", "uz", "ky"], &[]),
+    (&["en"], &[
+        ("one", "n % 10 = 1 and n % 100 != 11"),
+        ("two", "n % 10 = 2 and n % 100 != 12"),
+        ("few", "n % 10 = 3 and n % 100 != 13"),
+    ]),
+    (&["fr", "vi", "fil", "tl", "ms", "ro", "ga", "hy", "lo", "mo"], &[
+        ("one", "n = 1"),
+    ]),
+    (&["it", "sc", "scn"], &[
+        ("many", "n = 11,8,80,800"),
+    ]),
+    (&["ca"], &[
+        ("one", "n = 1,3"),
+        ("two", "n = 2"),
+        ("few", "n = 4"),
+    ]),
+    (&["sv"], &[
+        ("one", "n % 10 = 1,2 and n % 100 != 11,12"),
+    ]),
+    (&["hu"], &[
+        ("one", "n = 1,5"),
+    ]),
+    (&["uk"], &[
+        ("few", "n % 10 = 3 and n % 100 != 13"),
+    ]),
+    (&["kk"], &[
+        ("many", "n % 10 = 6 or n % 10 = 9 or n % 10 = 0 and n != 0"),
+    ]),
+    (&["mk"], &[
+        ("one", "i % 10 = 1 and i % 100 != 11"),
+        ("two", "i % 10 = 2 and i % 100 != 12"),
+        ("many", "i % 10 = 7,8 and i % 100 != 17,18"),
+    ]),
+    (&["hi", "gu"], &[
+        ("one", "n = 1"),
+        ("two", "n = 2,3"),
+        ("few", "n = 4"),
+        ("many", "n = 6"),
+    ]),
+    (&["bn", "as"], &[
+        ("one", "n = 1,5,7,8,9,10"),
+        ("two", "n = 2,3"),
+        ("few", "n = 4"),
+        ("many", "n = 6"),
+    ]),
+    (&["cy"], &[
+        ("zero", "n = 0,7,8,9"),
+        ("one", "n = 1"),
+        ("two", "n = 2"),
+        ("few", "n = 3,4"),
+        ("many", "n = 5,6"),
+    ]),
+];
+
+/// The CLDR plural operands of a number.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Operands {
+    /// Absolute value
+    pub n: f64,
+    /// Integer digits
+    pub i: u64,
+    /// Number of visible fraction digits, with trailing zeros
+    pub v: u64,
+    /// Number of visible fraction digits, without trailing zeros
+    pub w: u64,
+    /// Visible fraction digits, with trailing zeros
+    pub f: u64,
+    /// Visible fraction digits, without trailing zeros
+    pub t: u64
+}
+
+impl Operands {
+    /// Gets the operands from a decimal string (e.g. "1.50").
+    pub fn from_decimal_str(s: &str) -> Option<Operands> {
+        let s = s.trim().trim_start_matches('-');
+        let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+        if int_part.is_empty() || !int_part.bytes().all(|c| c.is_ascii_digit()) ||
+            !frac_part.bytes().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let frac_trimmed = frac_part.trim_end_matches('0');
+
+        Some(Operands {
+            n: s.parse().ok()?,
+            i: int_part.parse().ok()?,
+            v: frac_part.len() as u64,
+            w: frac_trimmed.len() as u64,
+            f: if frac_part.is_empty() { 0 } else { frac_part.parse().ok()? },
+            t: if frac_trimmed.is_empty() { 0 } else { frac_trimmed.parse().ok()? }
+        })
+    }
+
+    /// Gets the operands from a number shown with `fraction_digits` visible fraction digits.
+    pub fn from_f64(n: f64, fraction_digits: usize) -> Operands {
+        // Fixed precision formatting never uses the exponent notation
+        Self::from_decimal_str(&format!("{:.*}", fraction_digits, n.abs())).unwrap_or_default()
+    }
+
+    pub fn from_u64(n: u64) -> Operands {
+        Operands {
+            n: n as f64,
+            i: n,
+            ..Default::default()
+        }
+    }
+
+    fn get(&self, operand: Operand) -> f64 {
+        match operand {
+            Operand::N => self.n,
+            Operand::I => self.i as f64,
+            Operand::V => self.v as f64,
+            Operand::W => self.w as f64,
+            Operand::F => self.f as f64,
+            Operand::T => self.t as f64,
+            Operand::E => 0.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand { N, I, V, W, F, T, E }
+
+#[derive(Clone, Debug)]
+struct Relation {
+    operand: Operand,
+    modulo: Option<f64>,
+    negated: bool,
+    // Only integers are in range (false for "within")
+    integer_only: bool,
+    ranges: Vec<(f64, f64)>
+}
+
+impl Relation {
+    fn matches(&self, operands: &Operands) -> bool {
+        let mut value = operands.get(self.operand);
+        if let Some(modulo) = self.modulo {
+            value %= modulo;
+        }
+
+        let in_range = (!self.integer_only || value.fract() == 0.0) &&
+            self.ranges.iter().any(|(start, end)| value >= *start && value <= *end);
+        in_range != self.negated
+    }
+
+    /// Converts the relation to a gettext expression for integers, or its constant value
+    /// if it doesn't depend on n.
+    fn to_gettext(&self) -> Result<String, bool> {
+        if !matches!(self.operand, Operand::N | Operand::I) {
+            // The other operands are always 0 for integers
+            return Err(self.matches(&Operands::default()));
+        }
+
+        let value = match self.modulo {
+            Some(modulo) => format!("n % {}", modulo),
+            None => "n".to_owned()
+        };
+        if let [(start, end)] = self.ranges[..] {
+            if start == end {
+                return Ok(format!("{} {} {}", value, if self.negated { "!=" } else { "==" }, start));
+            }
+            if !self.negated {
+                return Ok(format!("{} >= {} && {} <= {}", value, start, value, end));
+            }
+        }
+
+        let ranges = self.ranges.iter()
+            .map(|(start, end)| if start == end {
+                format!("{} == {}", value, start)
+            }
+            else {
+                format!("{} >= {} && {} <= {}", value, start, value, end)
+            })
+            .collect::<Vec<_>>()
+            .join(" || ");
+        Ok(format!("{}({})", if self.negated { "!" } else { "" }, ranges))
+    }
+}
+
+/// A condition in disjunctive normal form (or of ands).
+#[derive(Clone, Debug)]
+struct Condition(Vec<Vec<Relation>>);
+
+impl Condition {
+    fn matches(&self, operands: &Operands) -> bool {
+        self.0.iter().any(|relations| relations.iter().all(|relation| relation.matches(operands)))
+    }
+
+    /// Same as Relation::to_gettext.
+    fn to_gettext(&self) -> Result<String, bool> {
+        let mut or_list = Vec::new();
+        'or: for relations in &self.0 {
+            let mut and_list = Vec::new();
+            for relation in relations {
+                match relation.to_gettext() {
+                    Ok(expr) => and_list.push(expr),
+                    Err(true) => (),
+                    Err(false) => continue 'or
+                }
+            }
+            if and_list.is_empty() {
+                return Err(true);
+            }
+            or_list.push(and_list);
+        }
+
+        match &or_list[..] {
+            [] => Err(false),
+            [and_list] => Ok(and_list.join(" && ")),
+            _ => Ok(or_list.iter()
+                .map(|and_list| match &and_list[..] {
+                    [expr] => expr.clone(),
+                    _ => format!("({})", and_list.join(" && "))
+                })
+                .collect::<Vec<_>>()
+                .join(" || "))
+        }
+    }
+
+    fn parse(src: &str) -> Result<Condition, Error> {
+        let mut tokens = tokenize(src).into_iter().peekable();
+        let mut or_list = Vec::new();
+        let mut and_list = Vec::new();
+
+        loop {
+            and_list.push(Self::parse_relation(&mut tokens)?);
+            match tokens.next() {
+                Some("and") => continue,
+                Some("or") => or_list.push(std::mem::take(&mut and_list)),
+                None => break,
+                Some(_) => return Err(Error::PluralParsing)
+            }
+        }
+        or_list.push(and_list);
+
+        Ok(Condition(or_list))
+    }
+
+    fn parse_relation<'a>(tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>) -> Result<Relation, Error> {
+        let operand = match tokens.next() {
+            Some("n") => Operand::N,
+            Some("i") => Operand::I,
+            Some("v") => Operand::V,
+            Some("w") => Operand::W,
+            Some("f") => Operand::F,
+            Some("t") => Operand::T,
+            Some("e") | Some("c") => Operand::E,
+            _ => return Err(Error::PluralParsing)
+        };
+
+        let modulo = if tokens.peek() == Some(&"%") || tokens.peek() == Some(&"mod") {
+            tokens.next();
+            Some(parse_value(tokens.next())?)
+        }
+        else {
+            None
+        };
+
+        let mut negated = false;
+        let mut integer_only = true;
+        loop {
+            match tokens.next() {
+                Some("=") | Some("in") | Some("is") => break,
+                Some("!=") => {
+                    negated = true;
+                    break;
+                }
+                Some("not") => negated = true,
+                Some("within") => {
+                    integer_only = false;
+                    break;
+                }
+                _ => return Err(Error::PluralParsing)
+            }
+        }
+        // "is not"
+        if tokens.peek() == Some(&"not") {
+            tokens.next();
+            negated = true;
+        }
+
+        let mut ranges = Vec::new();
+        loop {
+            let start = parse_value(tokens.next())?;
+            let end = if tokens.peek() == Some(&"..") {
+                tokens.next();
+                parse_value(tokens.next())?
+            }
+            else {
+                start
+            };
+            ranges.push((start, end));
+
+            if tokens.peek() != Some(&",") {
+                break;
+            }
+            tokens.next();
+        }
+
+        Ok(Relation { operand, modulo, negated, integer_only, ranges })
+    }
+}
+
+fn parse_value(token: Option<&str>) -> Result<f64, Error> {
+    token.and_then(|s| s.parse::<u64>().ok())
+        .map(|v| v as f64)
+        .ok_or(Error::PluralParsing)
+}
+
+fn tokenize(src: &str) -> Vec<&str> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b' ' | b'\t' => {
+                i += 1;
+                continue;
+            }
+            b'.' if bytes.get(i + 1) == Some(&b'.') => i += 2,
+            b'!' if bytes.get(i + 1) == Some(&b'=') => i += 2,
+            c if c.is_ascii_alphanumeric() => {
+                while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+            }
+            _ => i += 1
+        }
+        tokens.push(&src[start..i]);
+    }
+
+    tokens
+}
+
+#[derive(Clone, Debug)]
+pub struct Rules {
+    // Conditions of every category except other, which is the last index
+    conditions: Vec<Condition>
+}
+
+impl Rules {
+    fn find(table: &'static [(&[&str], &[(&str, &str)])], lang: &str) -> Option<&'static [(&'static str, &'static str)]> {
+        let lang = lang.to_ascii_lowercase().replace('_', "-");
+        let lookup = |lang: &str| table.iter()
+            .find(|(langs, _)| langs.contains(&lang))
+            .map(|(_, rules)| *rules);
+
+        // Try the full tag first (e.g. pt-PT), then the language only
+        lookup(&lang).or_else(|| lookup(lang.split('-').next()?))
+    }
+
+    fn new(table: &'static [(&[&str], &[(&str, &str)])], lang: &str) -> Result<Rules, Error> {
+        let Some(rules) = Self::find(table, lang) else {
+            return Err(Error::RuntimeError(format!("No CLDR plural rules for language: {}", lang)));
+        };
+
+        let mut conditions = Vec::with_capacity(rules.len());
+        for (_, condition) in rules {
+            conditions.push(Condition::parse(condition)?);
+        }
+        Ok(Rules { conditions })
+    }
+
+    pub fn cardinal(lang: &str) -> Result<Rules, Error> {
+        Self::new(CARDINAL_RULES, lang)
+    }
+
+    pub fn ordinal(lang: &str) -> Result<Rules, Error> {
+        Self::new(ORDINAL_RULES, lang)
+    }
+
+    /// Gettext Plural-Forms header value for these rules.
+    /// Only integers are supported by gettext, so the fraction rules are dropped.
+    pub fn to_gettext_plural_forms(&self) -> String {
+        let nplurals = self.conditions.len() + 1;
+        let mut plural = String::new();
+        // The last category (other) is used if none of the conditions match
+        let mut fallback = self.conditions.len();
+        for (i, condition) in self.conditions.iter().enumerate() {
+            match condition.to_gettext() {
+                Ok(expr) => plural += &format!("({}) ? {} : ", expr, i),
+                Err(true) => {
+                    fallback = i;
+                    break;
+                }
+                Err(false) => ()
+            }
+        }
+        plural += &fallback.to_string();
+
+        format!("nplurals={}; plural={};", nplurals, plural)
+    }
+
+    /// Returns the index of the plural category.
+    pub fn resolve(&self, operands: &Operands) -> usize {
+        self.conditions.iter()
+            .position(|condition| condition.matches(operands))
+            .unwrap_or(self.conditions.len())
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path, sync::{atomic::{self, AtomicBool}, Mutex}};
+
+use fnv::{FnvHashMap, FnvHashSet};
+use serde::Serialize;
+
+use super::{utils, Error};
+
+// Upper bound for the missed keys stored per source, misses on further keys are only counted
+const MAX_MISSED_KEYS: usize = 10000;
+// Upper bound for the hashes remembered by record_hashed, new hashes past it are not recorded
+const MAX_SEEN_HASHES: usize = 100000;
+
+/// Lookup site of a translation, each one is reported separately.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverageSource {
+    Localize,
+    Hashed,
+    TextData,
+    CharacterSystemText,
+    RaceJikkyoComment,
+    RaceJikkyoMessage,
+    SqlTable,
+    Story,
+    RaceStory,
+    Lyrics,
+}
+
+#[derive(Serialize, Default)]
+struct KeyCoverage {
+    hits: u64,
+    misses: u64,
+    // Original text, for keys that can't be mapped back to it (hashes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+#[derive(Default)]
+struct SourceCoverage {
+    keys: FnvHashMap<String, KeyCoverage>,
+    missed_keys: usize,
+    dropped_misses: u64,
+}
+
+/// Runtime hit/miss counts of translation lookups, per key.
+/// Only collected when `track_coverage` is enabled since every lookup allocates its key.
+#[derive(Default)]
+pub struct Coverage {
+    // Mirrors the config, so that lookups don't have to load it
+    enabled: AtomicBool,
+    entries: Mutex<FnvHashMap<CoverageSource, SourceCoverage>>,
+    // Hashed lookups run on every layout pass, so each hash is only recorded the first time it's seen
+    seen_hashes: Mutex<FnvHashSet<u64>>,
+}
+
+#[derive(Serialize)]
+struct SourceReport<'a> {
+    hits: u64,
+    misses: u64,
+    keys: usize,
+    missed_keys: usize,
+    dropped_misses: u64,
+    entries: BTreeMap<&'a str, &'a KeyCoverage>,
+}
+
+impl Coverage {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, atomic::Ordering::Relaxed);
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn record(&self, source: CoverageSource, key: impl Display, hit: bool) {
+        if !self.enabled() {
+            return;
+        }
+        self.record_inner(source, key.to_string(), hit, None::<fn() -> String>);
+    }
+
+    /// Records a hashed dict lookup once per distinct hash, saving the original text on a miss.
+    pub fn record_hashed(&self, hash: u64, hit: bool, text: impl FnOnce() -> String) {
+        if !self.enabled() {
+            return;
+        }
+        {
+            let mut seen_hashes = self.seen_hashes.lock().unwrap();
+            // Changing text (timers, counters...) would make the set grow forever otherwise
+            if seen_hashes.len() >= MAX_SEEN_HASHES || !seen_hashes.insert(hash) {
+                return;
+            }
+        }
+        self.record_inner(CoverageSource::Hashed, hash.to_string(), hit, Some(text));
+    }
+
+    fn record_inner(&self, source: CoverageSource, key: String, hit: bool, text: Option<impl FnOnce() -> String>) {
+        let mut entries = self.entries.lock().unwrap();
+        let source = entries.entry(source).or_default();
+        if !hit && !source.keys.contains_key(&key) && source.missed_keys >= MAX_MISSED_KEYS {
+            source.dropped_misses += 1;
+            return;
+        }
+
+        let entry = source.keys.entry(key).or_default();
+        if hit {
+            entry.hits += 1;
+        } else {
+            if entry.misses == 0 {
+                source.missed_keys += 1;
+            }
+            entry.misses += 1;
+            if entry.text.is_none() {
+                entry.text = text.map(|f| f());
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        self.seen_hashes.lock().unwrap().clear();
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let entries = self.entries.lock().unwrap();
+        let report: BTreeMap<CoverageSource, SourceReport> = entries
+            .iter()
+            .map(|(source, coverage)| {
+                let keys = &coverage.keys;
+                let report = SourceReport {
+                    hits: keys.values().map(|k| k.hits).sum(),
+                    misses: keys.values().map(|k| k.misses).sum::<u64>() + coverage.dropped_misses,
+                    keys: keys.len(),
+                    missed_keys: coverage.missed_keys,
+                    dropped_misses: coverage.dropped_misses,
+                    entries: keys.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+                };
+                (*source, report)
+            })
+            .collect();
+        utils::write_json_file(&report, path)
+    }
+}
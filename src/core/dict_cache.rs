@@ -0,0 +1,233 @@
+use std::{
+    fs,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use fnv::{FnvHashMap, FnvHashSet};
+use serde::de::DeserializeOwned;
+
+use super::Error;
+
+// Binary cache of parsed dicts, so that large JSON files don't have to be parsed on every launch.
+// Each cache file is keyed by the blake3 hash of its source JSON file. Files downloaded by the
+// translation updater use the hashes from the repo cache so they don't even have to be read,
+// other files are hashed. The source size and mtime are checked in case a repo file was edited,
+// in which case the cache file is rewritten under the same repo hash.
+//
+// Layout: MAGIC | blake3 hash (64, hex) | source size (u64) | source mtime (u64) | payload
+// All integers are little endian, maps are a u32 count followed by the entries,
+// strings are a u32 byte length followed by UTF-8 bytes.
+const MAGIC: &[u8; 4] = b"HDC2";
+const HASH_SIZE: usize = 64;
+const HEADER_SIZE: usize = 4 + HASH_SIZE + 8 + 8;
+
+pub trait CacheValue: Sized {
+    fn write(&self, buf: &mut Vec<u8>);
+    fn read(reader: &mut Reader) -> Option<Self>;
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.read_bytes(N)?.try_into().ok()
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        Some(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.read_array()?))
+    }
+}
+
+impl CacheValue for String {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let len = reader.read_u32()? as usize;
+        std::str::from_utf8(reader.read_bytes(len)?).ok().map(str::to_owned)
+    }
+}
+
+impl CacheValue for u64 {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        reader.read_u64()
+    }
+}
+
+impl CacheValue for i32 {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(i32::from_le_bytes(reader.read_array()?))
+    }
+}
+
+impl<K: CacheValue + Eq + Hash, V: CacheValue> CacheValue for FnvHashMap<K, V> {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        for (k, v) in self {
+            k.write(buf);
+            v.write(buf);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let len = reader.read_u32()? as usize;
+        // Don't trust the length for preallocation in case the file is corrupted
+        let mut map = FnvHashMap::with_capacity_and_hasher(len.min(reader.data.len()), Default::default());
+        for _ in 0..len {
+            let k = K::read(reader)?;
+            let v = V::read(reader)?;
+            map.insert(k, v);
+        }
+        Some(map)
+    }
+}
+
+pub struct DictCache {
+    dir: PathBuf,
+    // Repo file hashes keyed by the file's path on disk
+    repo_hashes: FnvHashMap<PathBuf, String>,
+    // Cache files that are used by the loaded dicts, the others are deleted by prune()
+    used: Mutex<FnvHashSet<PathBuf>>,
+}
+
+impl DictCache {
+    pub fn new(dir: PathBuf, repo_hashes: FnvHashMap<PathBuf, String>) -> DictCache {
+        DictCache {
+            dir,
+            repo_hashes,
+            used: Mutex::default(),
+        }
+    }
+
+    fn repo_hash(&self, json_path: &Path) -> Option<&String> {
+        self.repo_hashes
+            .get(json_path)
+            .filter(|hash| hash.len() == HASH_SIZE && hash.bytes().all(|c| c.is_ascii_hexdigit()))
+    }
+
+    fn cache_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", &hash[..32]))
+    }
+
+    /// Loads a dict from its binary cache, or parses the JSON file and caches it if it's not cached yet.
+    pub fn load<T: CacheValue + DeserializeOwned>(&self, json_path: &Path) -> Result<T, Error> {
+        let metadata = fs::metadata(json_path)?;
+        let size = metadata.len();
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        let mut json = None;
+        let hash = match self.repo_hash(json_path) {
+            Some(hash) => hash.to_ascii_lowercase(),
+            None => {
+                let data = fs::read(json_path)?;
+                let hash = blake3::hash(&data).to_hex().to_string();
+                json = Some(data);
+                hash
+            }
+        };
+
+        let path = self.cache_path(&hash);
+        let cached_dict = fs::read(&path)
+            .ok()
+            .filter(|c| {
+                c.len() >= HEADER_SIZE && c.starts_with(MAGIC) && c[MAGIC.len()..MAGIC.len() + HASH_SIZE] == *hash.as_bytes()
+            })
+            .and_then(|c| {
+                let mut reader = Reader { data: &c, pos: MAGIC.len() + HASH_SIZE };
+                if reader.read_u64()? != size || reader.read_u64()? != mtime {
+                    return None;
+                }
+                T::read(&mut reader)
+            });
+        if let Some(dict) = cached_dict {
+            self.used.lock().unwrap().insert(path);
+            return Ok(dict);
+        }
+
+        let json = match json {
+            Some(v) => v,
+            None => fs::read(json_path)?,
+        };
+        let dict = serde_json::from_slice(&json)?;
+
+        // Saved under the same key even if the repo hash is stale (the file was edited locally),
+        // the size and mtime tell whether it's still up to date on the next lookup
+        if let Err(e) = save(&path, &dict, &hash, size, mtime) {
+            warn!("Failed to save dict cache for '{}': {}", json_path.display(), e);
+        }
+        self.used.lock().unwrap().insert(path);
+
+        Ok(dict)
+    }
+
+    /// Deletes the cache files that aren't used by any of the loaded dicts.
+    pub fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let used = self.used.lock().unwrap();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if used.contains(&path) || !path.is_file() {
+                continue;
+            }
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to delete orphaned dict cache '{}': {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn save<T: CacheValue>(path: &Path, dict: &T, hash: &str, size: u64, mtime: u64) -> Result<(), Error> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE);
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(hash.as_bytes());
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.extend_from_slice(&mtime.to_le_bytes());
+    dict.write(&mut buf);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a temp file first so that a crash can't leave a truncated cache behind
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, &buf)?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
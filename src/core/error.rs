@@ -14,6 +14,7 @@ pub enum Error {
     GuiRendererInitError(String),
     HttpError(ureq::Error),
     PluralParsing,
+    PluralEvaluation(String),
     OutOfDiskSpace,
     FileHashMismatch(String),
     ZipError(zip::result::ZipError),
@@ -51,6 +52,9 @@ impl fmt::Display for Error {
             Error::PluralParsing => {
                 write!(f, "Failed to parse plural expression")
             }
+            Error::PluralEvaluation(msg) => {
+                write!(f, "Failed to evaluate plural expression: {}", msg)
+            }
             Error::HttpError(error) => {
                 write!(f, "HTTP error: {}", error)
             }
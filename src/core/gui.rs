@@ -23,6 +23,7 @@ use crate::il2cpp::{
             GraphicSettings::{GraphicsQuality, MsaaQuality},
             Localize,
         },
+        LibNative_Runtime::Sqlite3::Connection,
         UnityEngine_CoreModule::{Application, Texture::AnisoLevel},
     },
     symbols::Thread,
@@ -35,10 +36,14 @@ use crate::il2cpp::hook::umamusume::WebViewManager;
 use crate::il2cpp::hook::UnityEngine_CoreModule::QualitySettings;
 
 use super::{
-    hachimi::{self, Language},
+    hachimi::{self, DictKind, Language},
+    hashed_dict,
+    load_report::LoadStatus,
+    master_extract,
+    po,
     http::AsyncRequest,
     tl_repo::{self, RepoInfo},
-    utils, Hachimi,
+    utils, xliff, Hachimi,
 };
 
 macro_rules! add_font {
@@ -53,6 +58,14 @@ macro_rules! add_font {
 }
 
 type BoxedWindow = Box<dyn Window + Send + Sync>;
+
+const PO_FILENAME: &str = "localized_data.po";
+const XLIFF_DIR: &str = "xliff";
+const XLIFF_ORIGINALS_DIR: &str = "xliff_originals";
+const COVERAGE_FILENAME: &str = "coverage.json";
+const HASHED_DICT_SOURCE_FILENAME: &str = "hashed_dict_source.json";
+const MASTER_DB_FILENAME: &str = "master.mdb";
+
 pub struct Gui {
     pub context: egui::Context,
     pub input: egui::RawInput,
@@ -344,6 +357,8 @@ impl Gui {
         let localized_data = hachimi.localized_data.load();
         let localize_dict_count = localized_data.localize_dict.len().to_string();
         let hashed_dict_count = localized_data.hashed_dict.len().to_string();
+        let asset_dict_cache_stats = localized_data.asset_dict_cache.stats();
+        let sql_query_cache_stats = Connection::query_cache_stats();
 
         let mut show_notification: Option<Cow<'_, str>> = None;
         let mut show_window: Option<BoxedWindow> = None;
@@ -374,6 +389,18 @@ impl Gui {
                             count = localize_dict_count
                         ));
                         ui.label(t!("menu.hashed_dict_entries", count = hashed_dict_count));
+                        ui.label(t!(
+                            "menu.asset_dict_cache_stats",
+                            hits = asset_dict_cache_stats.hits,
+                            misses = asset_dict_cache_stats.misses,
+                            count = asset_dict_cache_stats.len
+                        ));
+                        ui.label(t!(
+                            "menu.sql_query_cache_stats",
+                            hits = sql_query_cache_stats.hits,
+                            misses = sql_query_cache_stats.misses,
+                            count = sql_query_cache_stats.len
+                        ));
                         ui.separator();
 
                         ui.heading(t!("menu.config_heading"));
@@ -464,8 +491,19 @@ impl Gui {
 
                         ui.heading(t!("menu.translation_heading"));
                         if ui.button(t!("menu.reload_localized_data")).clicked() {
-                            hachimi.load_localized_data();
-                            show_notification = Some(t!("notification.localized_data_reloaded"));
+                            show_notification = Some(match hachimi.load_localized_data() {
+                                Ok(_) => t!("notification.localized_data_reloaded"),
+                                Err(e) => e.to_string().into(),
+                            });
+                        }
+                        if ui
+                            .button(t!(
+                                "menu.view_load_report",
+                                count = localized_data.load_report.error_count()
+                            ))
+                            .clicked()
+                        {
+                            show_window = Some(Box::new(LoadReportWindow::new()));
                         }
                         if ui.button(t!("menu.check_for_updates")).clicked() {
                             hachimi.tl_updater.clone().check_for_updates(false);
@@ -473,6 +511,19 @@ impl Gui {
                         if ui.button(t!("menu.check_for_updates_pedantic")).clicked() {
                             hachimi.tl_updater.clone().check_for_updates(true);
                         }
+                        if hachimi.config.load().track_coverage {
+                            if ui.button(t!("menu.save_coverage_report")).clicked() {
+                                let path = hachimi.get_data_path(COVERAGE_FILENAME);
+                                show_notification = Some(match hachimi.coverage.save(&path) {
+                                    Ok(_) => t!("notification.saved_coverage_report", filename = COVERAGE_FILENAME),
+                                    Err(e) => e.to_string().into(),
+                                });
+                            }
+                            if ui.button(t!("menu.clear_coverage")).clicked() {
+                                hachimi.coverage.clear();
+                                show_notification = Some(t!("notification.coverage_cleared"));
+                            }
+                        }
                         if hachimi.config.load().translator_mode {
                             if ui.button(t!("menu.dump_localize_dict")).clicked() {
                                 Thread::main_thread().schedule(|| {
@@ -489,6 +540,151 @@ impl Gui {
                                     }
                                 })
                             }
+                            if ui.button(t!("menu.export_po")).clicked() {
+                                Thread::main_thread().schedule(|| {
+                                    let hachimi = Hachimi::instance();
+                                    let localized_data = hachimi.localized_data.load();
+                                    // text_data originals aren't available in-game, they're read from a copy of
+                                    // master.mdb or from dumps that are provided manually
+                                    let mut originals = po::Originals {
+                                        localize: Localize::dump_strings(),
+                                        text_data: read_optional_json(&hachimi.get_data_path("text_data_dump.json")),
+                                        character_system_text: read_optional_json(
+                                            &hachimi.get_data_path("character_system_text_dump.json")
+                                        ),
+                                    };
+                                    let master_db_path = hachimi.get_data_path(MASTER_DB_FILENAME);
+                                    if master_db_path.is_file() {
+                                        if let Err(e) = originals.fill_from_master_db(&master_db_path, &localized_data.config) {
+                                            warn!("Failed to read originals from {}: {}", MASTER_DB_FILENAME, e);
+                                        }
+                                    }
+                                    let path = hachimi.get_data_path(PO_FILENAME);
+                                    let res = po::export_to_file(&localized_data, &originals, &path);
+                                    let mut gui = Gui::instance().unwrap().lock().unwrap();
+                                    match res {
+                                        Ok(count) => gui.show_notification(&t!(
+                                            "notification.exported_po",
+                                            count = count,
+                                            filename = PO_FILENAME
+                                        )),
+                                        Err(e) => gui.show_notification(&e.to_string()),
+                                    }
+                                })
+                            }
+                            if ui.button(t!("menu.import_po")).clicked() {
+                                thread::spawn(|| {
+                                    let hachimi = Hachimi::instance();
+                                    let path = hachimi.get_data_path(PO_FILENAME);
+                                    let res = po::import_from_file(&hachimi.localized_data.load(), &path);
+                                    let notif = match res.and_then(|dicts| hachimi.load_localized_data().map(|_| dicts)) {
+                                        Ok(dicts) => t!(
+                                            "notification.imported_po",
+                                            count = dicts.len(),
+                                            skipped = dicts.skipped
+                                        )
+                                        .into_owned(),
+                                        Err(e) => e.to_string(),
+                                    };
+                                    Gui::instance().unwrap().lock().unwrap().show_notification(&notif);
+                                });
+                            }
+                            if ui.button(t!("menu.export_xliff")).clicked() {
+                                thread::spawn(|| {
+                                    let hachimi = Hachimi::instance();
+                                    // Story originals can't be dumped in-game, they can be provided manually
+                                    let originals_dir = hachimi.get_data_path(XLIFF_ORIGINALS_DIR);
+                                    let res = xliff::export_dir(
+                                        &hachimi.localized_data.load(),
+                                        Some(originals_dir.as_path()).filter(|p| p.is_dir()),
+                                        &hachimi.get_data_path(XLIFF_DIR),
+                                        Some(hachimi.config.load().language.locale_str())
+                                    );
+                                    let notif = match res {
+                                        Ok(count) => t!(
+                                            "notification.exported_xliff",
+                                            count = count,
+                                            dirname = XLIFF_DIR
+                                        )
+                                        .into_owned(),
+                                        Err(e) => e.to_string(),
+                                    };
+                                    Gui::instance().unwrap().lock().unwrap().show_notification(&notif);
+                                });
+                            }
+                            if ui.button(t!("menu.import_xliff")).clicked() {
+                                thread::spawn(|| {
+                                    let hachimi = Hachimi::instance();
+                                    let res = xliff::import_dir(
+                                        &hachimi.localized_data.load(),
+                                        &hachimi.get_data_path(XLIFF_DIR)
+                                    );
+                                    let notif = match res.and_then(|count| hachimi.load_localized_data().map(|_| count)) {
+                                        Ok(count) => t!("notification.imported_xliff", count = count).into_owned(),
+                                        Err(e) => e.to_string(),
+                                    };
+                                    Gui::instance().unwrap().lock().unwrap().show_notification(&notif);
+                                });
+                            }
+                            if ui.button(t!("menu.build_hashed_dict")).clicked() {
+                                thread::spawn(|| {
+                                    let hachimi = Hachimi::instance();
+                                    let Some(out_path) = hachimi.localized_data.load().get_dict_write_path(DictKind::Hashed)
+                                    else {
+                                        Gui::instance()
+                                            .unwrap()
+                                            .lock()
+                                            .unwrap()
+                                            .show_notification(&t!("notification.no_hashed_dict_configured"));
+                                        return;
+                                    };
+                                    let res = hashed_dict::build_file(
+                                        &hachimi.get_data_path(HASHED_DICT_SOURCE_FILENAME),
+                                        &out_path,
+                                        hashed_dict::VerifyMode::Source
+                                    );
+                                    let notif = match res {
+                                        Ok(res) => {
+                                            hachimi.reload_localized_dict(DictKind::Hashed);
+                                            t!(
+                                                "notification.built_hashed_dict",
+                                                count = res.dict.len(),
+                                                collisions = res.collisions
+                                            )
+                                            .into_owned()
+                                        }
+                                        Err(e) => e.to_string(),
+                                    };
+                                    Gui::instance().unwrap().lock().unwrap().show_notification(&notif);
+                                });
+                            }
+                            if ui.button(t!("menu.extract_master_db")).clicked() {
+                                thread::spawn(|| {
+                                    let hachimi = Hachimi::instance();
+                                    // Reads the copy of master.mdb in the data dir, the game's own database is never opened
+                                    let localized_data = hachimi.localized_data.load();
+                                    let tables = master_extract::tables_for(&localized_data.config);
+                                    let res = master_extract::extract(
+                                        &hachimi.get_data_path(MASTER_DB_FILENAME),
+                                        &tables,
+                                        &hachimi.get_data_path(master_extract::DEFAULT_OUT_DIR),
+                                        localized_data.main_dir()
+                                    );
+                                    let notif = match res {
+                                        Ok(summaries) => t!(
+                                            "notification.extracted_master_db",
+                                            count = summaries.len(),
+                                            dirname = master_extract::DEFAULT_OUT_DIR,
+                                            added = summaries.iter().map(|s| s.added).sum::<usize>(),
+                                            removed = summaries.iter().map(|s| s.removed).sum::<usize>(),
+                                            changed = summaries.iter().map(|s| s.changed).sum::<usize>()
+                                        )
+                                        .into_owned(),
+                                        Err(e) => e.to_string(),
+                                    };
+                                    Gui::instance().unwrap().lock().unwrap().show_notification(&notif);
+                                });
+                            }
                         }
                         ui.separator();
 
@@ -794,6 +990,13 @@ fn random_id() -> egui::Id {
     egui::Id::new(egui::epaint::ahash::RandomState::new().hash_one(0))
 }
 
+fn read_optional_json<T: serde::de::DeserializeOwned + Default>(path: &std::path::Path) -> T {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
 struct Notification {
     content: String,
     tween: TweenInOutWithDelay,
@@ -1261,6 +1464,16 @@ impl ConfigEditor {
                 ui.checkbox(&mut config.translator_mode, "");
                 ui.end_row();
 
+                if config.translator_mode {
+                    ui.label(t!("config_editor.watch_localized_data"));
+                    ui.checkbox(&mut config.watch_localized_data, "");
+                    ui.end_row();
+                }
+
+                ui.label(t!("config_editor.track_coverage"));
+                ui.checkbox(&mut config.track_coverage, "");
+                ui.end_row();
+
                 ui.label(t!("config_editor.skip_first_time_setup"));
                 ui.checkbox(&mut config.skip_first_time_setup, "");
                 ui.end_row();
@@ -1866,6 +2079,82 @@ impl Window for LicenseWindow {
     }
 }
 
+struct LoadReportWindow {
+    id: egui::Id,
+}
+
+impl LoadReportWindow {
+    fn new() -> LoadReportWindow {
+        LoadReportWindow { id: random_id() }
+    }
+}
+
+impl Window for LoadReportWindow {
+    fn run(&mut self, ctx: &egui::Context) -> bool {
+        let mut open = true;
+        let mut open2 = true;
+        // Always show the current report, it changes when the data is reloaded
+        let localized_data = Hachimi::instance().localized_data.load();
+        let report = &localized_data.load_report;
+
+        new_window(ctx, self.id, t!("load_report.title"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                simple_window_layout(
+                    ui,
+                    self.id,
+                    |ui| {
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            if report.entries.is_empty() {
+                                ui.label(t!("load_report.empty"));
+                            }
+                            for entry in report.entries.iter() {
+                                ui.label(format!("{:?}: {}", entry.source, entry.path.display()));
+                                match &entry.status {
+                                    LoadStatus::Loaded { entries: Some(count) } => {
+                                        ui.weak(t!("load_report.loaded_entries", count = count));
+                                    }
+                                    LoadStatus::Loaded { entries: None } => {
+                                        ui.weak(t!("load_report.loaded"));
+                                    }
+                                    LoadStatus::Missing => {
+                                        ui.colored_label(ui.visuals().warn_fg_color, t!("load_report.missing"));
+                                    }
+                                    LoadStatus::Error { message, .. } => {
+                                        ui.colored_label(ui.visuals().error_fg_color, message);
+                                    }
+                                }
+                                ui.separator();
+                            }
+                        });
+                    },
+                    |ui| {
+                        if ui.button(t!("ok")).clicked() {
+                            open2 = false;
+                        }
+                        if ui.button(t!("load_report.save_json")).clicked() {
+                            let path = Hachimi::instance().get_data_path("load_report.json");
+                            let notif = match utils::write_json_file(report, path) {
+                                Ok(_) => t!("notification.saved_load_report").into_owned(),
+                                Err(e) => e.to_string(),
+                            };
+                            // Same workaround as save_and_reload_config
+                            thread::spawn(move || {
+                                Gui::instance()
+                                    .unwrap()
+                                    .lock()
+                                    .unwrap()
+                                    .show_notification(&notif);
+                            });
+                        }
+                    },
+                );
+            });
+
+        open && open2
+    }
+}
+
 pub struct PersistentMessageWindow {
     id: egui::Id,
     title: String,
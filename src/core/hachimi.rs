@@ -14,8 +14,14 @@ use crate::{
 };
 
 use super::{
+    asset_dict_cache::AssetDictCache,
+    coverage::Coverage,
+    sql_capture::SqlCapture,
+    hashed_dict::HashedEntry,
+    sql_table::{self, SqlTableConfig, SqlTableDict},
+    load_report::{DictLen, LoadReport, LoadStatus, ReportSource},
     game::{Game, Region},
-    ipc, plurals, template, template_filters, tl_repo, utils, Error, Interceptor,
+    cldr_plurals, dict_cache, ipc, ld_watcher, plurals, template, template_filters, tl_repo, utils, Error, Interceptor,
 };
 
 pub struct Hachimi {
@@ -26,7 +32,12 @@ pub struct Hachimi {
 
     // Localized data
     pub localized_data: ArcSwap<LocalizedData>,
+    // Held while replacing the localized data, so that a single dict reload
+    // can't store data that's older than a concurrent full reload
+    localized_data_lock: Mutex<()>,
     pub tl_updater: Arc<tl_repo::Updater>,
+    pub coverage: Coverage,
+    pub sql_capture: SqlCapture,
 
     // Shared properties
     pub game: Game,
@@ -81,7 +92,7 @@ impl Hachimi {
 
         info!("Hachimi {}", env!("HACHIMI_DISPLAY_VERSION"));
         info!("Game region: {}", instance.game.region);
-        instance.load_localized_data();
+        _ = instance.load_localized_data();
 
         INSTANCE.set(Arc::new(instance)).is_ok()
     }
@@ -106,6 +117,9 @@ impl Hachimi {
 
         config.language.set_locale();
 
+        let coverage = Coverage::default();
+        coverage.set_enabled(config.track_coverage);
+
         Ok(Hachimi {
             interceptor: Interceptor::default(),
             hooking_finished: AtomicBool::new(false),
@@ -113,7 +127,10 @@ impl Hachimi {
 
             // Don't load localized data initially since it might fail, logging the error is not possible here
             localized_data: ArcSwap::default(),
+            localized_data_lock: Mutex::default(),
             tl_updater: Arc::default(),
+            coverage,
+            sql_capture: SqlCapture::default(),
 
             game,
             template_parser: template::Parser::new(&template_filters::LIST),
@@ -156,6 +173,7 @@ impl Hachimi {
         };
 
         new_config.language.set_locale();
+        self.coverage.set_enabled(new_config.track_coverage);
         self.config.store(Arc::new(new_config));
     }
 
@@ -171,25 +189,42 @@ impl Hachimi {
         self.save_config(&config)?;
 
         config.language.set_locale();
+        self.coverage.set_enabled(config.track_coverage);
         self.config.store(Arc::new(config));
         Ok(())
     }
 
-    pub fn load_localized_data(&self) {
+    pub fn load_localized_data(&self) -> Result<(), Error> {
+        let _guard = self.localized_data_lock.lock().unwrap();
         if self.tl_updater.progress().is_some() {
             warn!("Update in progress, not loading localized data");
+            return Err(Error::RuntimeError("Update in progress".to_owned()));
+        }
+        let new_data = LocalizedData::new(&self.config.load(), &self.game.data_dir)
+            .inspect_err(|e| error!("Failed to load localized data: {}", e))?;
+        self.localized_data.store(Arc::new(new_data));
+        // Cached SQL queries depend on the tables declared in the config
+        il2cpp::hook::LibNative_Runtime::Sqlite3::Connection::clear_query_cache();
+        Ok(())
+    }
+
+    pub fn reload_localized_dict(&self, kind: DictKind) {
+        let _guard = self.localized_data_lock.lock().unwrap();
+        if self.tl_updater.progress().is_some() {
+            warn!("Update in progress, not reloading localized data");
             return;
         }
-        let new_data = match LocalizedData::new(&self.config.load(), &self.game.data_dir) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Failed to load localized data: {}", e);
-                return;
-            }
-        };
+        let mut new_data = self.localized_data.load().as_ref().clone();
+        new_data.load_dict_kind(kind);
         self.localized_data.store(Arc::new(new_data));
     }
 
+    /// Empties the localized data, e.g. so that its files aren't accessed while they're being updated.
+    pub fn unload_localized_data(&self) {
+        let _guard = self.localized_data_lock.lock().unwrap();
+        self.localized_data.store(Arc::new(LocalizedData::default()));
+    }
+
     pub fn on_dlopen(&self, filename: &str, handle: usize) -> bool {
         // Prevent double initialization
         if self.hooking_finished.load(atomic::Ordering::Relaxed) {
@@ -227,6 +262,9 @@ impl Hachimi {
             ipc::start_http(config.ipc_listen_all);
         }
 
+        // Idles unless enabled in translator mode
+        ld_watcher::start();
+
         hachimi_impl::on_hooking_finished(self);
 
         for plugin in self.plugins.lock().unwrap().iter() {
@@ -269,6 +307,21 @@ fn default_serde_instance<'a, T: Deserialize<'a>>() -> Option<T> {
     T::deserialize(empty_deserializer).ok()
 }
 
+fn fill_missing_entries<K: Eq + std::hash::Hash, V>(dict: &mut FnvHashMap<K, V>, fallback: Option<FnvHashMap<K, V>>) {
+    for (k, v) in fallback.unwrap_or_default() {
+        dict.entry(k).or_insert(v);
+    }
+}
+
+fn fill_missing_nested_entries<K: Eq + std::hash::Hash, K2: Eq + std::hash::Hash, V>(
+    dict: &mut FnvHashMap<K, FnvHashMap<K2, V>>,
+    fallback: Option<FnvHashMap<K, FnvHashMap<K2, V>>>,
+) {
+    for (k, entries) in fallback.unwrap_or_default() {
+        fill_missing_entries(dict.entry(k).or_default(), Some(entries));
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Config {
     #[serde(default)]
@@ -276,10 +329,21 @@ pub struct Config {
     #[serde(default)]
     pub translator_mode: bool,
     #[serde(default)]
+    pub watch_localized_data: bool,
+    #[serde(default)]
+    pub track_coverage: bool,
+    #[serde(default)]
     pub disable_gui: bool,
     #[serde(default)]
     pub disable_gui_once: bool,
     pub localized_data_dir: Option<String>,
+    // Extra localized data dirs layered on top of localized_data_dir, highest priority first.
+    // These should live outside of localized_data_dir since the translation updater manages it.
+    #[serde(default = "Config::default_localized_data_override_dirs")]
+    pub localized_data_override_dirs: Vec<String>,
+    // Localized data dirs of other languages, consulted in order for entries missing from localized_data_dir.
+    #[serde(default)]
+    pub localized_data_fallback_dirs: Vec<String>,
     pub target_fps: Option<i32>,
     #[serde(default = "Config::default_open_browser_url")]
     pub open_browser_url: String,
@@ -349,6 +413,9 @@ pub struct Config {
 }
 
 impl Config {
+    fn default_localized_data_override_dirs() -> Vec<String> {
+        vec!["localized_data_overrides".to_owned()]
+    }
     fn default_open_browser_url() -> String {
         "https://www.google.com/".to_owned()
     }
@@ -467,18 +534,29 @@ impl Language {
     }
 }
 
-#[derive(Default)]
+const DICT_CACHE_DIR: &str = "dict_cache";
+
+#[derive(Default, Clone)]
 pub struct LocalizedData {
     pub config: LocalizedDataConfig,
-    path: Option<PathBuf>,
-
-    pub localize_dict: FnvHashMap<String, String>,
-    pub hashed_dict: FnvHashMap<u64, String>,
-    pub text_data_dict: FnvHashMap<i32, FnvHashMap<i32, String>>, // {"category": {"index": "text"}}
-    pub character_system_text_dict: FnvHashMap<i32, FnvHashMap<i32, String>>, // {"character_id": {"voice_id": "text"}}
-    pub race_jikkyo_comment_dict: FnvHashMap<i32, String>,                    // {"id": "text"}
-    pub race_jikkyo_message_dict: FnvHashMap<i32, String>,                    // {"id": "text"}
-    assets_path: Option<PathBuf>,
+    // Data dir stack, highest priority first. The last one is the main localized data dir.
+    paths: Vec<PathBuf>,
+    fallbacks: Vec<(PathBuf, LocalizedDataConfig)>,
+    dict_cache: Option<Arc<dict_cache::DictCache>>,
+    pub load_report: LoadReport,
+
+    // Dicts are shared between instances so that a single one can be reloaded cheaply
+    pub localize_dict: Arc<FnvHashMap<String, String>>,
+    pub hashed_dict: Arc<FnvHashMap<u64, HashedEntry>>, // {"hash": "text" | {"text", "source"?, "source_hash"?} | [...]}
+    pub text_data_dict: Arc<FnvHashMap<i32, FnvHashMap<i32, String>>>, // {"category": {"index": "text"}}
+    pub character_system_text_dict: Arc<FnvHashMap<i32, FnvHashMap<i32, String>>>, // {"character_id": {"voice_id": "text"}}
+    pub race_jikkyo_comment_dict: Arc<FnvHashMap<i32, String>>,                    // {"id": "text"}
+    pub race_jikkyo_message_dict: Arc<FnvHashMap<i32, String>>,                    // {"id": "text"}
+    pub sql_table_dicts: Arc<FnvHashMap<String, SqlTableDict>>,                    // {"table": dict}
+    assets_paths: Vec<PathBuf>,
+    fallback_assets_paths: Vec<PathBuf>,
+    // Shared with clones since reloading a single dict doesn't affect the assets
+    pub asset_dict_cache: Arc<AssetDictCache>,
 
     pub plural_form: plurals::Resolver,
     pub ordinal_form: plurals::Resolver,
@@ -486,13 +564,97 @@ pub struct LocalizedData {
     pub wrapper_penalties: Penalties,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DictKind {
+    Localize,
+    Hashed,
+    TextData,
+    CharacterSystemText,
+    RaceJikkyoComment,
+    RaceJikkyoMessage,
+}
+
+impl DictKind {
+    pub const ALL: &[Self] = &[
+        Self::Localize,
+        Self::Hashed,
+        Self::TextData,
+        Self::CharacterSystemText,
+        Self::RaceJikkyoComment,
+        Self::RaceJikkyoMessage,
+    ];
+
+    pub fn config_path(self, config: &LocalizedDataConfig) -> Option<&String> {
+        match self {
+            Self::Localize => config.localize_dict.as_ref(),
+            Self::Hashed => config.hashed_dict.as_ref(),
+            Self::TextData => config.text_data_dict.as_ref(),
+            Self::CharacterSystemText => config.character_system_text_dict.as_ref(),
+            Self::RaceJikkyoComment => config.race_jikkyo_comment_dict.as_ref(),
+            Self::RaceJikkyoMessage => config.race_jikkyo_message_dict.as_ref(),
+        }
+    }
+
+    /// Checks that a dict file can be parsed as this kind of dict.
+    pub fn check_file(self, path: &Path) -> Result<(), Error> {
+        let json = fs::read_to_string(path)?;
+        match self {
+            Self::Localize => _ = serde_json::from_str::<FnvHashMap<String, String>>(&json)?,
+            Self::Hashed => _ = serde_json::from_str::<FnvHashMap<u64, HashedEntry>>(&json)?,
+            Self::TextData | Self::CharacterSystemText => {
+                _ = serde_json::from_str::<FnvHashMap<i32, FnvHashMap<i32, String>>>(&json)?
+            }
+            Self::RaceJikkyoComment | Self::RaceJikkyoMessage => {
+                _ = serde_json::from_str::<FnvHashMap<i32, String>>(&json)?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum WatchedFileKind {
+    /// Changes affect everything, the whole data must be reloaded
+    Config,
+    /// SQL table dicts aren't reloaded individually either
+    SqlTableDict,
+    Dict(DictKind),
+    /// Cached asset dicts are revalidated on their next load
+    AssetDict,
+}
+
+impl WatchedFileKind {
+    /// Checks that a file can be parsed as this kind of file.
+    pub fn check_file(self, path: &Path) -> Result<(), Error> {
+        match self {
+            Self::Config => _ = LocalizedData::try_read_dict_file::<LocalizedDataConfig>(path)?,
+            Self::SqlTableDict => _ = LocalizedData::try_read_dict_file::<SqlTableDict>(path)?,
+            Self::Dict(kind) => kind.check_file(path)?,
+            // Asset dicts come in many shapes
+            Self::AssetDict => _ = LocalizedData::try_read_dict_file::<serde_json::Value>(path)?,
+        }
+        Ok(())
+    }
+}
+
 impl LocalizedData {
     fn new(config: &Config, data_dir: &Path) -> Result<LocalizedData, Error> {
         if config.disable_translations {
             return Ok(LocalizedData::default());
         }
 
-        let path: Option<PathBuf>;
+        // Override dirs that don't exist are simply skipped
+        let mut paths: Vec<PathBuf> = config
+            .localized_data_override_dirs
+            .iter()
+            .map(|dir| data_dir.join(dir))
+            .filter(|p| p.is_dir())
+            .collect();
+        let fallback_dirs = config.localized_data_fallback_dirs.clone();
+
+        // A broken config shouldn't take down the dicts with it, the error is shown in the load report instead
+        let mut config_error = None;
         let config: LocalizedDataConfig = if let Some(ld_dir) = &config.localized_data_dir {
             let ld_path = Path::new(data_dir).join(ld_dir);
 
@@ -505,51 +667,38 @@ impl LocalizedData {
                     .open(ld_path.join(".nomedia"));
             }
 
-            let ld_config_path = ld_path.join("config.json");
-            path = Some(ld_path);
-
-            if fs::metadata(&ld_config_path).is_ok() {
-                let json = fs::read_to_string(&ld_config_path)?;
-                serde_json::from_str(&json)?
-            } else {
-                warn!("Localized data config not found");
+            // The localized data config is only read from the main dir
+            let ld_config = Self::load_config(&ld_path).unwrap_or_else(|e| {
+                error!("Failed to load localized data config in '{}': {}", ld_path.display(), e);
+                config_error = Some(LoadStatus::from_error(&e));
                 LocalizedDataConfig::default()
-            }
+            });
+            paths.push(ld_path);
+            ld_config
         } else {
-            path = None;
+            paths.clear();
             LocalizedDataConfig::default()
         };
 
-        let plural_form = Self::parse_plural_form_or_default(&config.plural_form)?;
-        let ordinal_form = Self::parse_plural_form_or_default(&config.ordinal_form)?;
+        // Bad plural rules fall back to the default one and are shown in the load report, same as the config
+        let mut plural_errors = Vec::new();
+        let mut parse_plural_form = |name: &'static str, opt: &Option<String>, ordinal: bool| {
+            Self::parse_plural_form_or_default(opt, ordinal).unwrap_or_else(|e| {
+                error!("Invalid {}: {}", name, e);
+                plural_errors.push((name, e));
+                plurals::Resolver::default()
+            })
+        };
+        let plural_form = parse_plural_form("plural_form", &config.plural_form, false);
+        let ordinal_form = parse_plural_form("ordinal_form", &config.ordinal_form, true);
 
         let wrapper_penalties = Self::parse_wrap_penalties_or_default(&config.wrapper_penalties);
 
-        Ok(LocalizedData {
-            localize_dict: Self::load_dict_static(&path, config.localize_dict.as_ref())
-                .unwrap_or_default(),
-            hashed_dict: Self::load_dict_static(&path, config.hashed_dict.as_ref())
-                .unwrap_or_default(),
-            text_data_dict: Self::load_dict_static(&path, config.text_data_dict.as_ref())
-                .unwrap_or_default(),
-            character_system_text_dict: Self::load_dict_static(
-                &path,
-                config.character_system_text_dict.as_ref(),
-            )
-            .unwrap_or_default(),
-            race_jikkyo_comment_dict: Self::load_dict_static(
-                &path,
-                config.race_jikkyo_comment_dict.as_ref(),
-            )
-            .unwrap_or_default(),
-            race_jikkyo_message_dict: Self::load_dict_static(
-                &path,
-                config.race_jikkyo_message_dict.as_ref(),
-            )
-            .unwrap_or_default(),
-            assets_path: path
+        let mut data = LocalizedData {
+            assets_paths: config
+                .assets_dir
                 .as_ref()
-                .map(|p| config.assets_dir.as_ref().map(|dir| p.join(dir)))
+                .map(|dir| paths.iter().map(|p| p.join(dir)).collect())
                 .unwrap_or_default(),
 
             plural_form,
@@ -558,67 +707,302 @@ impl LocalizedData {
             wrapper_penalties,
 
             config,
-            path,
-        })
+            paths,
+            dict_cache: Some(Arc::new(dict_cache::DictCache::new(
+                data_dir.join(DICT_CACHE_DIR),
+                tl_repo::load_repo_hashes(data_dir),
+            ))),
+            ..Default::default()
+        };
+
+        if let Some(main_path) = data.paths.last() {
+            let config_path = main_path.join("config.json");
+            let status = config_error.unwrap_or_else(|| Self::file_status(&config_path));
+            data.load_report.push(config_path.clone(), ReportSource::Config, status);
+            for (name, e) in plural_errors {
+                let status = LoadStatus::Error { message: format!("{}: {}", name, e), line: None, column: None };
+                data.load_report.push(config_path.clone(), ReportSource::PluralRule, status);
+            }
+        }
+        if let Some(main_assets_path) = data.assets_paths.last() {
+            if !main_assets_path.is_dir() {
+                data.load_report.push(main_assets_path.clone(), ReportSource::AssetsDir, LoadStatus::Missing);
+            }
+        }
+        if let Some(bundle_path) = data.config.extra_asset_bundle.as_ref().and_then(|p| data.get_data_path(p)) {
+            let status = Self::file_status(&bundle_path);
+            data.load_report.push(bundle_path, ReportSource::ExtraAssetBundle, status);
+        }
+
+        for fallback_dir in &fallback_dirs {
+            let fallback_path = data_dir.join(fallback_dir);
+            let config_path = fallback_path.join("config.json");
+            match data.add_fallback(fallback_path.clone()) {
+                Ok(_) => {
+                    let status = Self::file_status(&config_path);
+                    data.load_report.push(config_path, ReportSource::Config, status);
+                }
+                Err(e) => {
+                    error!("Failed to load fallback localized data '{}': {}", fallback_path.display(), e);
+                    data.load_report.push(config_path, ReportSource::Config, LoadStatus::from_error(&e));
+                }
+            }
+        }
+
+        for &kind in DictKind::ALL {
+            data.load_dict_kind(kind);
+        }
+        data.load_sql_table_dicts();
+
+        if let Some(dict_cache) = &data.dict_cache {
+            dict_cache.prune();
+        }
+
+        Ok(data)
     }
 
-    fn load_dict_static_ex<T: DeserializeOwned, P: AsRef<Path>>(
-        ld_path_opt: &Option<PathBuf>,
-        rel_path_opt: Option<P>,
-        silent_fs_error: bool,
-    ) -> Option<T> {
-        let Some(ld_path) = ld_path_opt else {
-            return None;
-        };
-        let Some(rel_path) = rel_path_opt else {
-            return None;
-        };
+    fn file_status(path: &Path) -> LoadStatus {
+        if path.is_file() {
+            LoadStatus::Loaded { entries: None }
+        } else {
+            LoadStatus::Missing
+        }
+    }
 
-        let path = ld_path.join(rel_path);
-        let json = match fs::read_to_string(&path) {
-            Ok(v) => v,
-            Err(e) => {
-                if !silent_fs_error {
-                    error!("Failed to read '{}': {}", path.display(), e);
+    fn load_config(ld_path: &Path) -> Result<LocalizedDataConfig, Error> {
+        let ld_config_path = ld_path.join("config.json");
+        if fs::metadata(&ld_config_path).is_ok() {
+            let json = fs::read_to_string(&ld_config_path)?;
+            let mut config: LocalizedDataConfig = serde_json::from_str(&json)?;
+            // Replacing a builtin handler would lose its extra processing (e.g. skill name fitting)
+            config.sql_tables.retain(|t| {
+                let builtin = sql_table::BUILTIN_TABLES.contains(&t.table.as_str());
+                if builtin {
+                    warn!("Ignoring sql table '{}' in '{}': it has a builtin handler", t.table, ld_config_path.display());
                 }
-                return None;
+                !builtin
+            });
+            Ok(config)
+        } else {
+            warn!("Localized data config not found in '{}'", ld_path.display());
+            Ok(LocalizedDataConfig::default())
+        }
+    }
+
+    /// Adds another localized data dir to fill in entries that are missing from the dicts.
+    /// Its asset files are used when none of the main layers provide them.
+    fn add_fallback(&mut self, fallback_path: PathBuf) -> Result<(), Error> {
+        let config = Self::load_config(&fallback_path)?;
+        if let Some(dir) = &config.assets_dir {
+            self.fallback_assets_paths.push(fallback_path.join(dir));
+        }
+        self.fallbacks.push((fallback_path, config));
+        Ok(())
+    }
+
+    /// (Re)loads a dict from the data dir stack and the fallbacks.
+    pub fn load_dict_kind(&mut self, kind: DictKind) {
+        let mut report = std::mem::take(&mut self.load_report);
+        report.remove_source(ReportSource::Dict(kind));
+
+        match kind {
+            DictKind::Localize => {
+                self.localize_dict = Arc::new(self.load_layered_dict(kind, fill_missing_entries, &mut report))
             }
-        };
+            DictKind::Hashed => {
+                self.hashed_dict = Arc::new(self.load_layered_dict(kind, fill_missing_entries, &mut report))
+            }
+            DictKind::TextData => {
+                self.text_data_dict =
+                    Arc::new(self.load_layered_dict(kind, fill_missing_nested_entries, &mut report))
+            }
+            DictKind::CharacterSystemText => {
+                self.character_system_text_dict =
+                    Arc::new(self.load_layered_dict(kind, fill_missing_nested_entries, &mut report))
+            }
+            DictKind::RaceJikkyoComment => {
+                self.race_jikkyo_comment_dict =
+                    Arc::new(self.load_layered_dict(kind, fill_missing_entries, &mut report))
+            }
+            DictKind::RaceJikkyoMessage => {
+                self.race_jikkyo_message_dict =
+                    Arc::new(self.load_layered_dict(kind, fill_missing_entries, &mut report))
+            }
+        }
 
-        let dict = match serde_json::from_str::<T>(&json) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Failed to parse '{}': {}", path.display(), e);
-                return None;
+        self.load_report = report;
+    }
+
+    fn load_sql_table_dicts(&mut self) {
+        let mut report = std::mem::take(&mut self.load_report);
+        report.remove_source(ReportSource::SqlTable);
+
+        let mut dicts = FnvHashMap::default();
+        for table in &self.config.sql_tables {
+            let mut dict: SqlTableDict = self
+                .load_reported_dict(&self.paths, Some(&table.dict), ReportSource::SqlTable, &mut report)
+                .unwrap_or_default();
+            for (path, config) in &self.fallbacks {
+                let rel_path = config.sql_tables.iter().find(|t| t.table == table.table).map(|t| &t.dict);
+                dict.fill_missing(self.load_reported_dict(
+                    std::slice::from_ref(path),
+                    rel_path,
+                    ReportSource::SqlTable,
+                    &mut report,
+                ));
             }
-        };
+            dicts.insert(table.table.clone(), dict);
+        }
 
-        Some(dict)
+        self.sql_table_dicts = Arc::new(dicts);
+        self.load_report = report;
     }
 
-    fn load_dict_static<T: DeserializeOwned, P: AsRef<Path>>(
-        ld_path_opt: &Option<PathBuf>,
-        rel_path_opt: Option<P>,
-    ) -> Option<T> {
-        Self::load_dict_static_ex(ld_path_opt, rel_path_opt, false)
+    fn load_layered_dict<T: DeserializeOwned + dict_cache::CacheValue + DictLen + Default>(
+        &self,
+        kind: DictKind,
+        fill: fn(&mut T, Option<T>),
+        report: &mut LoadReport,
+    ) -> T {
+        let mut dict: T = self
+            .load_reported_dict(&self.paths, kind.config_path(&self.config), ReportSource::Dict(kind), report)
+            .unwrap_or_default();
+        for (path, config) in &self.fallbacks {
+            fill(
+                &mut dict,
+                self.load_reported_dict(
+                    std::slice::from_ref(path),
+                    kind.config_path(config),
+                    ReportSource::Dict(kind),
+                    report,
+                ),
+            );
+        }
+        dict
     }
 
-    pub fn load_dict<T: DeserializeOwned, P: AsRef<Path>>(
+    /// Loads a dict from the data dir stack, merging the override layers per key on top of the
+    /// main dict, and records the outcome of each file in the report.
+    /// Dicts that come from a single file go through the binary dict cache.
+    fn load_reported_dict<T: DeserializeOwned + dict_cache::CacheValue + DictLen>(
         &self,
-        rel_path_opt: Option<P>,
+        ld_paths: &[PathBuf],
+        rel_path_opt: Option<&String>,
+        source: ReportSource,
+        report: &mut LoadReport,
     ) -> Option<T> {
-        Self::load_dict_static(&self.path, rel_path_opt)
+        let rel_path = rel_path_opt?;
+        let (main_path, override_paths) = ld_paths.split_last()?;
+        let main_file = main_path.join(rel_path);
+
+        let layer_paths: Vec<PathBuf> = override_paths
+            .iter()
+            .map(|p| p.join(rel_path))
+            .filter(|p| p.is_file())
+            .collect();
+        if layer_paths.is_empty() {
+            let res = match &self.dict_cache {
+                Some(dict_cache) => dict_cache.load(&main_file),
+                None => Self::try_read_dict_file(&main_file),
+            };
+            return report.record(main_file, source, res, T::entry_count);
+        }
+
+        // Merged dicts are not cached.
+        // The main dict is allowed to be missing if an override provides it.
+        let mut merged = serde_json::Value::Null;
+        for path in std::iter::once(main_file).chain(layer_paths.into_iter().rev()) {
+            let res = Self::try_read_dict_file::<serde_json::Value>(&path);
+            if let Some(layer) = report.record(path, source, res, serde_json::Value::entry_count) {
+                utils::merge_json_value(&mut merged, layer);
+            }
+        }
+
+        match serde_json::from_value(merged) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!("Failed to parse merged '{}': {}", rel_path, e);
+                report.push(main_path.join(rel_path), source, LoadStatus::from_error(&Error::JsonParseError(e)));
+                None
+            }
+        }
+    }
+
+    /// Files that the loaded data depends on, along with what they are used for.
+    pub fn watched_files(&self) -> Vec<(PathBuf, WatchedFileKind)> {
+        let mut files = Vec::new();
+        if let Some(main_path) = self.paths.last() {
+            files.push((main_path.join("config.json"), WatchedFileKind::Config));
+        }
+        for (path, _) in &self.fallbacks {
+            files.push((path.join("config.json"), WatchedFileKind::Config));
+        }
+
+        for &kind in DictKind::ALL {
+            if let Some(rel_path) = kind.config_path(&self.config) {
+                files.extend(self.paths.iter().map(|p| (p.join(rel_path), WatchedFileKind::Dict(kind))));
+            }
+            for (path, config) in &self.fallbacks {
+                if let Some(rel_path) = kind.config_path(config) {
+                    files.push((path.join(rel_path), WatchedFileKind::Dict(kind)));
+                }
+            }
+        }
+
+        for table in &self.config.sql_tables {
+            files.extend(self.paths.iter().map(|p| (p.join(&table.dict), WatchedFileKind::SqlTableDict)));
+        }
+        for (path, config) in &self.fallbacks {
+            files.extend(config.sql_tables.iter().map(|t| (path.join(&t.dict), WatchedFileKind::SqlTableDict)));
+        }
+
+        // Only the asset dicts that have been loaded so far, there could be thousands of them
+        files.extend(self.asset_dict_cache.paths().into_iter().map(|p| (p, WatchedFileKind::AssetDict)));
+
+        files
+    }
+
+    fn try_read_dict_file<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str::<T>(&json)?)
+    }
+
+    fn read_dict_file<T: DeserializeOwned>(path: &Path, silent_fs_error: bool) -> Option<T> {
+        match Self::try_read_dict_file(path) {
+            Ok(v) => Some(v),
+            Err(Error::IoError(e)) => {
+                if !silent_fs_error {
+                    error!("Failed to read '{}': {}", path.display(), e);
+                }
+                None
+            }
+            Err(e) => {
+                error!("Failed to parse '{}': {}", path.display(), e);
+                None
+            }
+        }
     }
 
-    pub fn load_assets_dict<T: DeserializeOwned, P: AsRef<Path>>(
+    pub fn load_assets_dict<T: DeserializeOwned + Send + Sync + 'static, P: AsRef<Path>>(
         &self,
         rel_path_opt: Option<P>,
-    ) -> Option<T> {
-        Self::load_dict_static_ex(&self.assets_path, rel_path_opt, true)
+    ) -> Option<Arc<T>> {
+        let path = self.get_assets_path(rel_path_opt?)?;
+        self.asset_dict_cache
+            .get_or_load(&path, || Self::read_dict_file(&path, true))
     }
 
-    fn parse_plural_form_or_default(opt: &Option<String>) -> Result<plurals::Resolver, Error> {
+    fn parse_plural_form_or_default(opt: &Option<String>, ordinal: bool) -> Result<plurals::Resolver, Error> {
         if let Some(plural_form) = opt {
+            // Built-in CLDR rules, e.g. "cldr:ru"
+            if let Some(lang) = plural_form.strip_prefix("cldr:") {
+                let rules = if ordinal {
+                    cldr_plurals::Rules::ordinal(lang)?
+                } else {
+                    cldr_plurals::Rules::cardinal(lang)?
+                };
+                return Ok(plurals::Resolver::Cldr(rules));
+            }
             Ok(plurals::Resolver::Expr(plurals::Ast::parse(plural_form)?))
         } else {
             Ok(plurals::Resolver::Function(|_| 0))
@@ -638,30 +1022,61 @@ impl LocalizedData {
         }
     }
 
+    /// Returns the path from the highest priority layer that has the file, or the path in the main dir.
+    fn find_layered_path(ld_paths: &[PathBuf], fallback_paths: &[PathBuf], rel_path: &Path) -> Option<PathBuf> {
+        let main_path = ld_paths.last().map(|p| p.join(rel_path));
+        if ld_paths.len() <= 1 && fallback_paths.is_empty() {
+            return main_path;
+        }
+
+        ld_paths
+            .iter()
+            .chain(fallback_paths)
+            .map(|p| p.join(rel_path))
+            .find(|p| p.exists())
+            .or(main_path)
+    }
+
     pub fn get_assets_path<P: AsRef<Path>>(&self, rel_path: P) -> Option<PathBuf> {
-        self.assets_path.as_ref().map(|p| p.join(rel_path))
+        // Asset files are overridden as a whole
+        Self::find_layered_path(&self.assets_paths, &self.fallback_assets_paths, rel_path.as_ref())
+    }
+
+    /// Assets dirs of all layers, highest priority first.
+    pub fn assets_dirs(&self) -> &[PathBuf] {
+        &self.assets_paths
     }
 
     pub fn get_data_path<P: AsRef<Path>>(&self, rel_path: P) -> Option<PathBuf> {
-        self.path.as_ref().map(|p| p.join(rel_path))
+        Self::find_layered_path(&self.paths, &[], rel_path.as_ref())
+    }
+
+    /// The localized data dir itself, without the override dirs.
+    pub fn main_dir(&self) -> Option<&Path> {
+        self.paths.last().map(|p| p.as_path())
+    }
+
+    /// Path of a dict in the highest priority dir, where local edits should be written to.
+    pub fn get_dict_write_path(&self, kind: DictKind) -> Option<PathBuf> {
+        Some(self.paths.first()?.join(kind.config_path(&self.config)?))
     }
 
     pub fn load_asset_metadata<P: AsRef<Path>>(&self, rel_path: P) -> AssetMetadata {
         let mut path = rel_path.as_ref().to_owned();
         path.set_extension("json");
-        self.load_assets_dict(Some(path))
-            .unwrap_or_else(|| AssetInfo::<()>::default())
-            .metadata()
+        self.load_assets_dict::<AssetInfo<()>, _>(Some(path))
+            .map(|info| info.metadata_ref().clone())
+            .unwrap_or_default()
     }
 
-    pub fn load_asset_info<P: AsRef<Path>, T: DeserializeOwned>(
+    pub fn load_asset_info<P: AsRef<Path>, T: DeserializeOwned + Send + Sync + 'static>(
         &self,
         rel_path: P,
-    ) -> AssetInfo<T> {
+    ) -> Arc<AssetInfo<T>> {
         let mut path = rel_path.as_ref().to_owned();
         path.set_extension("json");
         self.load_assets_dict(Some(path))
-            .unwrap_or_else(|| AssetInfo::default())
+            .unwrap_or_default()
     }
 }
 
@@ -673,6 +1088,9 @@ pub struct LocalizedDataConfig {
     pub character_system_text_dict: Option<String>,
     pub race_jikkyo_comment_dict: Option<String>,
     pub race_jikkyo_message_dict: Option<String>,
+    // Extra master.mdb tables to translate
+    #[serde(default)]
+    pub sql_tables: Vec<SqlTableConfig>,
     pub assets_dir: Option<String>,
     #[serde(default)]
     pub extra_asset_bundle: OsOption<String>,
@@ -685,6 +1103,16 @@ pub struct LocalizedDataConfig {
     #[serde(default)]
     pub months: Vec<String>,
     pub month_text_format: Option<String>,
+    // Default values for identifier keys used in $(select ...), e.g. {"trainer_gender": "female"}
+    #[serde(default)]
+    pub select_defaults: FnvHashMap<String, String>,
+    #[serde(default)]
+    pub number_format: NumberFormat,
+    #[serde(default)]
+    pub duration_format: DurationFormat,
+    // Template evaluated with the $year, $month, $day and $weekday (1 = Monday) variables,
+    // e.g. "$(month $month) $day, $year". Defaults to YYYY-MM-DD.
+    pub date_format: Option<String>,
 
     #[serde(default)]
     pub use_text_wrapper: bool,
@@ -805,6 +1233,101 @@ pub struct PenaltiesConfig {
     hyphen_penalty: usize,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct NumberFormat {
+    #[serde(default = "NumberFormat::default_grouping_separator")]
+    pub grouping_separator: String,
+    #[serde(default = "NumberFormat::default_decimal_separator")]
+    pub decimal_separator: String,
+    // Set to 0 to disable digit grouping
+    #[serde(default = "NumberFormat::default_grouping_size")]
+    pub grouping_size: usize,
+    #[serde(default = "NumberFormat::default_compact_forms")]
+    pub compact_forms: Vec<CompactNumberForm>,
+    #[serde(default = "NumberFormat::default_compact_decimals")]
+    pub compact_decimals: usize,
+}
+
+impl NumberFormat {
+    fn default_grouping_separator() -> String {
+        ",".to_owned()
+    }
+    fn default_decimal_separator() -> String {
+        ".".to_owned()
+    }
+    fn default_grouping_size() -> usize {
+        3
+    }
+    fn default_compact_forms() -> Vec<CompactNumberForm> {
+        vec![
+            CompactNumberForm { threshold: 1e3, suffix: "K".to_owned() },
+            CompactNumberForm { threshold: 1e6, suffix: "M".to_owned() },
+            CompactNumberForm { threshold: 1e9, suffix: "B".to_owned() },
+        ]
+    }
+    fn default_compact_decimals() -> usize {
+        1
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        default_serde_instance().expect("default instance")
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CompactNumberForm {
+    // e.g. 1000 for thousands
+    pub threshold: f64,
+    // e.g. "K" or " k"
+    pub suffix: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct DurationFormat {
+    #[serde(default)]
+    pub short: DurationUnits,
+    // Falls back to the short units
+    pub long: Option<DurationUnits>,
+    #[serde(default = "DurationFormat::default_separator")]
+    pub separator: String,
+}
+
+impl DurationFormat {
+    fn default_separator() -> String {
+        " ".to_owned()
+    }
+}
+
+impl Default for DurationFormat {
+    fn default() -> Self {
+        default_serde_instance().expect("default instance")
+    }
+}
+
+// Each unit is a list of plural forms (resolved with plural_form), "$" is replaced with the value.
+// e.g. "hours": ["$ hour", "$ hours"]
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DurationUnits {
+    pub days: Vec<String>,
+    pub hours: Vec<String>,
+    pub minutes: Vec<String>,
+    pub seconds: Vec<String>,
+}
+
+impl Default for DurationUnits {
+    fn default() -> Self {
+        DurationUnits {
+            days: vec!["$d".to_owned()],
+            hours: vec!["$h".to_owned()],
+            minutes: vec!["$m".to_owned()],
+            seconds: vec!["$s".to_owned()],
+        }
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct SkillFormatting {
     #[serde(default = "SkillFormatting::default_length")]
@@ -0,0 +1,230 @@
+use std::{collections::BTreeMap, hash::Hasher, path::Path};
+
+use fnv::{FnvHashMap, FnvHasher};
+use serde::{Deserialize, Serialize};
+use widestring::Utf16Str;
+
+use super::{
+    dict_cache::{CacheValue, Reader},
+    load_report::DictLen,
+    utils, Error,
+};
+
+/// Value of a hashed dict entry.
+/// Plain strings are trusted as is, verified entries are only used if the source text matches,
+/// and multiple entries can share the same hash when their sources collide.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum HashedEntry {
+    Text(String),
+    Verified(VerifiedEntry),
+    Colliding(Vec<VerifiedEntry>),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VerifiedEntry {
+    pub text: String,
+    // Either the original text or its secondary hash, original text takes precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyMode {
+    #[default]
+    Source,
+    SourceHash,
+    None,
+}
+
+/// Hash used as the dict key, FNV-1a over the UTF-16 code units (same as Il2CppStringExt::hash).
+pub fn hash_utf16(s: &Utf16Str) -> u64 {
+    let mut hasher = FnvHasher::default();
+    for c in s.as_slice() {
+        hasher.write(&c.to_le_bytes());
+    }
+    hasher.finish()
+}
+
+/// Secondary hash to verify matches without storing the original text.
+pub fn source_hash(s: &Utf16Str) -> u64 {
+    let mut hasher = blake3::Hasher::new();
+    for c in s.as_slice() {
+        hasher.update(&c.to_le_bytes());
+    }
+    let hash = hasher.finalize();
+    u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap())
+}
+
+impl VerifiedEntry {
+    fn matches(&self, s: &Utf16Str) -> bool {
+        if let Some(source) = &self.source {
+            s.chars().eq(source.chars())
+        } else if let Some(hash) = self.source_hash {
+            source_hash(s) == hash
+        } else {
+            true
+        }
+    }
+}
+
+impl HashedEntry {
+    /// Translation for `s`, whose hash matched this entry.
+    pub fn get(&self, s: &Utf16Str) -> Option<&String> {
+        match self {
+            HashedEntry::Text(text) => Some(text),
+            HashedEntry::Verified(entry) => entry.matches(s).then_some(&entry.text),
+            HashedEntry::Colliding(entries) => entries.iter().find(|e| e.matches(s)).map(|e| &e.text),
+        }
+    }
+}
+
+impl DictLen for HashedEntry {
+    fn entry_count(&self) -> usize {
+        match self {
+            HashedEntry::Colliding(entries) => entries.len(),
+            _ => 1,
+        }
+    }
+}
+
+impl CacheValue for VerifiedEntry {
+    fn write(&self, buf: &mut Vec<u8>) {
+        self.text.write(buf);
+        match &self.source {
+            Some(source) => {
+                buf.push(1);
+                source.write(buf);
+            }
+            None => buf.push(0),
+        }
+        match self.source_hash {
+            Some(hash) => {
+                buf.push(1);
+                hash.write(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let text = String::read(reader)?;
+        let source = match reader.read_u8()? {
+            0 => None,
+            _ => Some(String::read(reader)?),
+        };
+        let source_hash = match reader.read_u8()? {
+            0 => None,
+            _ => Some(u64::read(reader)?),
+        };
+        Some(VerifiedEntry { text, source, source_hash })
+    }
+}
+
+impl CacheValue for HashedEntry {
+    fn write(&self, buf: &mut Vec<u8>) {
+        match self {
+            HashedEntry::Text(text) => {
+                buf.push(0);
+                text.write(buf);
+            }
+            HashedEntry::Verified(entry) => {
+                buf.push(1);
+                entry.write(buf);
+            }
+            HashedEntry::Colliding(entries) => {
+                buf.push(2);
+                buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+                for entry in entries {
+                    entry.write(buf);
+                }
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(match reader.read_u8()? {
+            0 => HashedEntry::Text(String::read(reader)?),
+            1 => HashedEntry::Verified(VerifiedEntry::read(reader)?),
+            2 => {
+                let len = reader.read_u32()? as usize;
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    entries.push(VerifiedEntry::read(reader)?);
+                }
+                HashedEntry::Colliding(entries)
+            }
+            _ => return None,
+        })
+    }
+}
+
+pub struct BuildResult {
+    pub dict: BTreeMap<u64, HashedEntry>,
+    /// Number of hashes shared by more than one original text.
+    pub collisions: usize,
+}
+
+/// Builds a hashed dict from a plain `{"original": "translation"}` dict.
+/// Colliding originals are always stored with their source text so that they can be told apart.
+pub fn build(plain: &FnvHashMap<String, String>, verify: VerifyMode) -> BuildResult {
+    let mut groups: BTreeMap<u64, Vec<(&String, &String)>> = BTreeMap::new();
+    for (original, text) in plain {
+        let utf16 = widestring::Utf16String::from_str(original);
+        groups.entry(hash_utf16(&utf16)).or_default().push((original, text));
+    }
+
+    let mut collisions = 0;
+    let dict = groups
+        .into_iter()
+        .map(|(hash, mut group)| {
+            let entry = if group.len() > 1 {
+                collisions += 1;
+                warn!("Hash collision for {}: {:?}", hash, group.iter().map(|(o, _)| o).collect::<Vec<_>>());
+                group.sort();
+                HashedEntry::Colliding(
+                    group
+                        .into_iter()
+                        .map(|(original, text)| VerifiedEntry {
+                            text: text.clone(),
+                            source: Some(original.clone()),
+                            source_hash: None,
+                        })
+                        .collect(),
+                )
+            } else {
+                let (original, text) = group[0];
+                match verify {
+                    VerifyMode::Source => HashedEntry::Verified(VerifiedEntry {
+                        text: text.clone(),
+                        source: Some(original.clone()),
+                        source_hash: None,
+                    }),
+                    VerifyMode::SourceHash => HashedEntry::Verified(VerifiedEntry {
+                        text: text.clone(),
+                        source: None,
+                        source_hash: Some(source_hash(&widestring::Utf16String::from_str(original))),
+                    }),
+                    VerifyMode::None => HashedEntry::Text(text.clone()),
+                }
+            };
+            (hash, entry)
+        })
+        .collect();
+
+    BuildResult { dict, collisions }
+}
+
+/// Builds a hashed dict from a plain dict file and writes it to `out_path`.
+pub fn build_file(in_path: &Path, out_path: &Path, verify: VerifyMode) -> Result<BuildResult, Error> {
+    let plain: FnvHashMap<String, String> = serde_json::from_str(&std::fs::read_to_string(in_path)?)?;
+    let res = build(&plain, verify);
+    if let Some(parent) = out_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    utils::write_json_file(&res.dict, out_path)?;
+    Ok(res)
+}
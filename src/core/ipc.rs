@@ -1,12 +1,16 @@
-use std::sync::{Condvar, Mutex};
+use std::{collections::BTreeMap, sync::{Condvar, Mutex}};
 
+use fnv::FnvHashMap;
 use rust_i18n::t;
 use serde::{Deserialize, Serialize};
 use tiny_http::{Header, Method, Request, Response, Server};
 
 use crate::{core::utils::notify_error, il2cpp::{hook::umamusume::{StoryTimelineController, StoryTimelineData}, symbols::{IList, Thread}}};
 
-use super::{Error, Gui, Hachimi};
+use super::{
+    hashed_dict::{self, HashedEntry, VerifyMode}, load_report::LoadReport,
+    master_extract::{self, TableSummary}, Error, Gui, Hachimi
+};
 
 pub fn start_http(listen_all: bool) {
     std::thread::spawn(move || http_thread(listen_all));
@@ -147,11 +151,36 @@ fn on_http_request(request: &mut Request) -> Result<CommandResponse, Error> {
         },
 
         Command::ReloadLocalizedData => {
-            Hachimi::instance().load_localized_data();
+            if let Err(e) = Hachimi::instance().load_localized_data() {
+                return Ok(CommandResponse::error(e.to_string()));
+            }
             if let Some(mutex) = Gui::instance() {
                 mutex.lock().unwrap().show_notification(&t!("notification.localized_data_reloaded"));
             }
         }
+
+        Command::GetLoadReport => {
+            let report = Hachimi::instance().localized_data.load().load_report.clone();
+            return Ok(CommandResponse::LoadReport { report });
+        }
+
+        Command::BuildHashedDict { dict, verify } => {
+            let res = hashed_dict::build(&dict, verify);
+            return Ok(CommandResponse::HashedDict { dict: res.dict, collisions: res.collisions });
+        }
+
+        Command::ExtractMasterDb { path, out_dir, compare_dir } => {
+            let hachimi = Hachimi::instance();
+            let localized_data = hachimi.localized_data.load();
+            let tables = master_extract::tables_for(&localized_data.config);
+            let out_dir = hachimi.get_data_path(out_dir.as_deref().unwrap_or(master_extract::DEFAULT_OUT_DIR));
+            // Compares against the localized data dir's dicts unless told otherwise
+            let compare_dir = compare_dir
+                .map(|d| hachimi.get_data_path(d))
+                .or_else(|| localized_data.main_dir().map(|p| p.to_owned()));
+            let tables = master_extract::extract(&hachimi.get_data_path(path), &tables, &out_dir, compare_dir.as_deref())?;
+            return Ok(CommandResponse::MasterDbExtract { tables });
+        }
     }
 
     Ok(CommandResponse::Ok)
@@ -182,7 +211,24 @@ enum Command {
         incremental: bool
     },
 
-    ReloadLocalizedData
+    ReloadLocalizedData,
+
+    GetLoadReport,
+
+    // {"original": "translation"} -> hashed dict
+    BuildHashedDict {
+        dict: FnvHashMap<String, String>,
+        #[serde(default)]
+        verify: VerifyMode
+    },
+
+    // Reads a copy of master.mdb and writes dict skeletons + diffs against the previous ones.
+    // Relative paths are resolved from the data dir.
+    ExtractMasterDb {
+        path: String,
+        out_dir: Option<String>,
+        compare_dir: Option<String>
+    }
 }
 
 #[derive(Serialize)]
@@ -196,6 +242,19 @@ enum CommandResponse {
 
     HelloWorld {
         message: &'static str
+    },
+
+    LoadReport {
+        report: LoadReport
+    },
+
+    HashedDict {
+        dict: BTreeMap<u64, HashedEntry>,
+        collisions: usize
+    },
+
+    MasterDbExtract {
+        tables: Vec<TableSummary>
     }
 }
 
@@ -0,0 +1,107 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use fnv::{FnvHashMap, FnvHashSet};
+use rust_i18n::t;
+
+use super::{hachimi::WatchedFileKind, utils::notify_error, Gui, Hachimi};
+
+// Polling is used instead of native fs events since it works the same way on every platform,
+// and only the dict files (not the whole assets dir) need to be checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn start() {
+    std::thread::spawn(|| {
+        let mut mtimes: FnvHashMap<PathBuf, Option<SystemTime>> = FnvHashMap::default();
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let hachimi = Hachimi::instance();
+            let config = hachimi.config.load();
+            if !config.translator_mode || !config.watch_localized_data {
+                mtimes.clear();
+                continue;
+            }
+
+            poll(&hachimi, &mut mtimes);
+        }
+    });
+}
+
+fn poll(hachimi: &Hachimi, mtimes: &mut FnvHashMap<PathBuf, Option<SystemTime>>) {
+    // Newly watched files are only recorded, missing files are tracked so that creating them counts as a change
+    let mut changed = Vec::new();
+    let mut new_mtimes = FnvHashMap::default();
+    for (path, kind) in hachimi.localized_data.load().watched_files() {
+        let mtime = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if mtimes.get(&path).is_some_and(|prev| *prev != mtime) {
+            changed.push((path.clone(), kind));
+        }
+        new_mtimes.insert(path, mtime);
+    }
+    *mtimes = new_mtimes;
+
+    if changed.is_empty() {
+        return;
+    }
+
+    // Keep the currently loaded data if any of its changed files is broken
+    let mut failed_kinds = FnvHashSet::default();
+    for (path, kind) in changed.iter() {
+        // Deleted files are fine, the data is just reloaded without them
+        if !path.exists() {
+            continue;
+        }
+        match kind.check_file(path) {
+            // Cached asset dicts are revalidated by themselves, there's nothing to reload
+            Ok(_) if *kind == WatchedFileKind::AssetDict => {
+                show_notification(&t!("notification.localized_asset_dict_changed", path = path.display()));
+            }
+            Ok(_) => (),
+            Err(e) => {
+                notify_error(t!(
+                    "notification.localized_dict_reload_failed",
+                    path = path.display(),
+                    reason = e
+                ));
+                failed_kinds.insert(*kind);
+            }
+        }
+    }
+
+    // Config and SQL table dict changes can affect everything
+    let full_reload_kinds = [WatchedFileKind::Config, WatchedFileKind::SqlTableDict];
+    if changed.iter().any(|(_, kind)| full_reload_kinds.contains(kind)) {
+        if full_reload_kinds.iter().any(|kind| failed_kinds.contains(kind)) {
+            return;
+        }
+
+        info!("Localized data config changed, reloading everything");
+        match hachimi.load_localized_data() {
+            Ok(_) => show_notification(&t!("notification.localized_data_reloaded")),
+            Err(e) => notify_error(t!("notification.localized_data_reload_failed", reason = e)),
+        }
+        return;
+    }
+
+    let mut reloaded_kinds = FnvHashSet::default();
+    for (path, kind) in changed {
+        let WatchedFileKind::Dict(kind) = kind else { continue };
+        if failed_kinds.contains(&WatchedFileKind::Dict(kind)) || !reloaded_kinds.insert(kind) {
+            continue;
+        }
+
+        info!("Reloading {:?} dict ({} changed)", kind, path.display());
+        hachimi.reload_localized_dict(kind);
+        show_notification(&t!("notification.localized_dict_reloaded", path = path.display()));
+    }
+}
+
+fn show_notification(content: &str) {
+    if let Some(mutex) = Gui::instance() {
+        mutex.lock().unwrap().show_notification(content);
+    }
+}
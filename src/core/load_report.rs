@@ -0,0 +1,130 @@
+use std::{io, path::PathBuf};
+
+use fnv::FnvHashMap;
+use serde::Serialize;
+
+use super::{hachimi::DictKind, Error};
+
+/// Outcome of every file that was (or should have been) loaded for the current localized data.
+#[derive(Serialize, Clone, Default)]
+pub struct LoadReport {
+    pub entries: Vec<LoadReportEntry>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LoadReportEntry {
+    pub path: PathBuf,
+    pub source: ReportSource,
+    #[serde(flatten)]
+    pub status: LoadStatus,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportSource {
+    Config,
+    PluralRule,
+    Dict(DictKind),
+    SqlTable,
+    AssetsDir,
+    ExtraAssetBundle,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LoadStatus {
+    Loaded {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        entries: Option<usize>,
+    },
+    Missing,
+    Error {
+        message: String,
+        // Only available for JSON errors
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        column: Option<usize>,
+    },
+}
+
+impl LoadStatus {
+    pub fn from_error(e: &Error) -> Self {
+        let (line, column) = match e {
+            Error::JsonParseError(e) if !e.is_io() => (Some(e.line()), Some(e.column())),
+            _ => (None, None),
+        };
+        LoadStatus::Error {
+            message: e.to_string(),
+            line,
+            column,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, LoadStatus::Error { .. })
+    }
+}
+
+impl LoadReport {
+    pub fn push(&mut self, path: PathBuf, source: ReportSource, status: LoadStatus) {
+        self.entries.push(LoadReportEntry { path, source, status });
+    }
+
+    /// Records the result of loading a file, logging the error if there is one.
+    pub fn record<T>(
+        &mut self,
+        path: PathBuf,
+        source: ReportSource,
+        res: Result<T, Error>,
+        count: fn(&T) -> usize,
+    ) -> Option<T> {
+        let (status, value) = match res {
+            Ok(v) => (LoadStatus::Loaded { entries: Some(count(&v)) }, Some(v)),
+            Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("File not found: '{}'", path.display());
+                (LoadStatus::Missing, None)
+            }
+            Err(e) => {
+                error!("Failed to load '{}': {}", path.display(), e);
+                (LoadStatus::from_error(&e), None)
+            }
+        };
+        self.push(path, source, status);
+        value
+    }
+
+    pub fn remove_source(&mut self, source: ReportSource) {
+        self.entries.retain(|e| e.source != source);
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.entries.iter().filter(|e| e.status.is_error()).count()
+    }
+}
+
+/// Number of translated strings in a dict, nested dicts are counted by their leaves.
+pub trait DictLen {
+    fn entry_count(&self) -> usize;
+}
+
+impl DictLen for String {
+    fn entry_count(&self) -> usize {
+        1
+    }
+}
+
+impl<K, V: DictLen> DictLen for FnvHashMap<K, V> {
+    fn entry_count(&self) -> usize {
+        self.values().map(DictLen::entry_count).sum()
+    }
+}
+
+impl DictLen for serde_json::Value {
+    fn entry_count(&self) -> usize {
+        match self {
+            serde_json::Value::Object(map) => map.values().map(DictLen::entry_count).sum(),
+            _ => 1,
+        }
+    }
+}
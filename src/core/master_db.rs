@@ -0,0 +1,400 @@
+//! Minimal read-only SQLite reader, used to extract text from a copy of the game's master.mdb.
+//! Only supports what's needed for that: full scans of rowid tables.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use super::Error;
+
+const HEADER_MAGIC: &[u8] = b"SQLite format 3\0";
+
+const PAGE_INTERIOR_TABLE: u8 = 0x05;
+const PAGE_LEAF_TABLE: u8 = 0x0D;
+
+// Guards against reference loops in corrupted files
+const MAX_TREE_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// String form used as a dict key or source text. Blobs have none.
+    pub fn to_key(&self) -> Option<String> {
+        match self {
+            Value::Null | Value::Blob(_) => None,
+            Value::Int(n) => Some(n.to_string()),
+            Value::Real(n) => Some(n.to_string()),
+            Value::Text(s) => Some(s.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+pub struct Table {
+    pub columns: Vec<String>,
+    root_page: u32,
+    // Index of the INTEGER PRIMARY KEY column, which is stored as the rowid instead of in the record
+    rowid_column: Option<usize>,
+}
+
+impl Table {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.eq_ignore_ascii_case(name))
+    }
+}
+
+pub struct MasterDb {
+    file: File,
+    page_size: usize,
+    usable_size: usize,
+    encoding: TextEncoding,
+}
+
+fn format_error(msg: impl std::fmt::Display) -> Error {
+    Error::RuntimeError(format!("Invalid database file: {}", msg))
+}
+
+impl MasterDb {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; 100];
+        file.read_exact(&mut header)?;
+        if &header[..16] != HEADER_MAGIC {
+            return Err(format_error("not an SQLite database (or it's encrypted)"));
+        }
+
+        let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+            1 => 65536,
+            n if n >= 512 && n.is_power_of_two() => n as usize,
+            n => return Err(format_error(format_args!("bad page size {}", n))),
+        };
+        let usable_size = page_size - header[20] as usize;
+        let encoding = match u32::from_be_bytes(header[56..60].try_into().unwrap()) {
+            0 | 1 => TextEncoding::Utf8,
+            2 => TextEncoding::Utf16Le,
+            3 => TextEncoding::Utf16Be,
+            n => return Err(format_error(format_args!("bad text encoding {}", n))),
+        };
+
+        Ok(MasterDb { file, page_size, usable_size, encoding })
+    }
+
+    fn read_page(&mut self, page_no: u32) -> Result<Vec<u8>, Error> {
+        if page_no == 0 {
+            return Err(format_error("null page reference"));
+        }
+        let mut page = vec![0u8; self.page_size];
+        self.file.seek(SeekFrom::Start((page_no as u64 - 1) * self.page_size as u64))?;
+        self.file.read_exact(&mut page)?;
+        Ok(page)
+    }
+
+    /// Looks up a table's columns and location from the schema table.
+    pub fn table(&mut self, name: &str) -> Result<Table, Error> {
+        let schema = Table { columns: Vec::new(), root_page: 1, rowid_column: None };
+        let mut table = None;
+        self.scan(&schema, |row| {
+            // type, name, tbl_name, rootpage, sql
+            let (Some(Value::Text(kind)), Some(Value::Text(tbl_name)), Some(Value::Int(root_page)), Some(Value::Text(sql))) =
+                (row.first(), row.get(1), row.get(3), row.get(4))
+            else {
+                return;
+            };
+            if kind == "table" && tbl_name.eq_ignore_ascii_case(name) && table.is_none() {
+                table = Some((*root_page as u32, sql.clone()));
+            }
+        })?;
+
+        let Some((root_page, sql)) = table else {
+            return Err(Error::RuntimeError(format!("Table not found: {}", name)));
+        };
+        parse_create_table(&sql, root_page)
+    }
+
+    /// Calls `f` with the values of each row of the table, in rowid order.
+    pub fn scan(&mut self, table: &Table, mut f: impl FnMut(&[Value])) -> Result<(), Error> {
+        self.scan_page(table, table.root_page, 0, &mut f)
+    }
+
+    fn scan_page(&mut self, table: &Table, page_no: u32, depth: usize, f: &mut impl FnMut(&[Value])) -> Result<(), Error> {
+        if depth > MAX_TREE_DEPTH {
+            return Err(format_error("b-tree is too deep"));
+        }
+
+        let page = self.read_page(page_no)?;
+        // Page 1 starts with the database header
+        let header_start = if page_no == 1 { 100 } else { 0 };
+        let header = page.get(header_start..header_start + 12).ok_or_else(|| format_error("truncated page"))?;
+        let page_type = header[0];
+        let cell_count = u16::from_be_bytes([header[3], header[4]]) as usize;
+
+        let (cell_ptrs_start, right_child) = match page_type {
+            PAGE_INTERIOR_TABLE => (header_start + 12, Some(u32::from_be_bytes(header[8..12].try_into().unwrap()))),
+            PAGE_LEAF_TABLE => (header_start + 8, None),
+            _ => return Err(format_error(format_args!("unsupported b-tree page type {}", page_type))),
+        };
+
+        for i in 0..cell_count {
+            let ptr_offset = cell_ptrs_start + i * 2;
+            let ptr = page.get(ptr_offset..ptr_offset + 2).ok_or_else(|| format_error("truncated page"))?;
+            let cell = page
+                .get(u16::from_be_bytes([ptr[0], ptr[1]]) as usize..)
+                .ok_or_else(|| format_error("bad cell pointer"))?;
+
+            if page_type == PAGE_INTERIOR_TABLE {
+                let left_child = cell.get(..4).ok_or_else(|| format_error("truncated cell"))?;
+                self.scan_page(table, u32::from_be_bytes(left_child.try_into().unwrap()), depth + 1, f)?;
+            } else {
+                let (payload_size, n) = read_varint(cell)?;
+                let (rowid, m) = read_varint(&cell[n..])?;
+                let payload = self.read_payload(&cell[n + m..], payload_size as usize)?;
+                let mut values = self.decode_record(&payload)?;
+                if let Some(idx) = table.rowid_column {
+                    if values.len() <= idx {
+                        values.resize(idx + 1, Value::Null);
+                    }
+                    values[idx] = Value::Int(rowid as i64);
+                }
+                // Rows written before an ALTER TABLE ADD COLUMN have fewer values
+                if values.len() < table.columns.len() {
+                    values.resize(table.columns.len(), Value::Null);
+                }
+                f(&values);
+            }
+        }
+
+        if let Some(right_child) = right_child {
+            self.scan_page(table, right_child, depth + 1, f)?;
+        }
+        Ok(())
+    }
+
+    fn read_payload(&mut self, local: &[u8], payload_size: usize) -> Result<Vec<u8>, Error> {
+        let usable = self.usable_size;
+        let max_local = usable - 35;
+        if payload_size <= max_local {
+            return local.get(..payload_size).map(|p| p.to_vec()).ok_or_else(|| format_error("truncated cell"));
+        }
+
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let k = min_local + (payload_size - min_local) % (usable - 4);
+        let local_size = if k <= max_local { k } else { min_local };
+
+        let mut payload = Vec::with_capacity(payload_size);
+        payload.extend_from_slice(local.get(..local_size).ok_or_else(|| format_error("truncated cell"))?);
+        let overflow = local.get(local_size..local_size + 4).ok_or_else(|| format_error("truncated cell"))?;
+        let mut next_page = u32::from_be_bytes(overflow.try_into().unwrap());
+        while payload.len() < payload_size {
+            let page = self.read_page(next_page)?;
+            next_page = u32::from_be_bytes(page[..4].try_into().unwrap());
+            let len = (payload_size - payload.len()).min(usable - 4);
+            payload.extend_from_slice(&page[4..4 + len]);
+        }
+        Ok(payload)
+    }
+
+    fn decode_record(&self, payload: &[u8]) -> Result<Vec<Value>, Error> {
+        let (header_size, mut header_pos) = read_varint(payload)?;
+        let header_size = header_size as usize;
+        let mut body_pos = header_size;
+        let mut values = Vec::new();
+
+        while header_pos < header_size {
+            let (serial_type, n) = read_varint(payload.get(header_pos..).unwrap_or_default())?;
+            header_pos += n;
+
+            let size = match serial_type {
+                0 | 8 | 9 => 0,
+                1..=4 => serial_type as usize,
+                5 => 6,
+                6 | 7 => 8,
+                n if n >= 12 => (n as usize - 12) / 2,
+                n => return Err(format_error(format_args!("bad serial type {}", n))),
+            };
+            let data = payload.get(body_pos..body_pos + size).ok_or_else(|| format_error("truncated record"))?;
+            body_pos += size;
+
+            values.push(match serial_type {
+                0 => Value::Null,
+                1..=6 => {
+                    // Big-endian two's complement, sign extended from the first byte
+                    let mut n = if data[0] & 0x80 != 0 { -1i64 } else { 0 };
+                    for b in data {
+                        n = (n << 8) | *b as i64;
+                    }
+                    Value::Int(n)
+                }
+                7 => Value::Real(f64::from_be_bytes(data.try_into().unwrap())),
+                8 => Value::Int(0),
+                9 => Value::Int(1),
+                n if n % 2 == 0 => Value::Blob(data.to_vec()),
+                _ => Value::Text(self.decode_text(data)),
+            });
+        }
+
+        Ok(values)
+    }
+
+    fn decode_text(&self, data: &[u8]) -> String {
+        match self.encoding {
+            TextEncoding::Utf8 => String::from_utf8_lossy(data).into_owned(),
+            TextEncoding::Utf16Le => {
+                String::from_utf16_lossy(&data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>())
+            }
+            TextEncoding::Utf16Be => {
+                String::from_utf16_lossy(&data.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>())
+            }
+        }
+    }
+}
+
+fn read_varint(buf: &[u8]) -> Result<(u64, usize), Error> {
+    let mut n = 0u64;
+    for i in 0..9 {
+        let b = *buf.get(i).ok_or_else(|| format_error("truncated varint"))?;
+        if i == 8 {
+            return Ok(((n << 8) | b as u64, 9));
+        }
+        n = (n << 7) | (b & 0x7F) as u64;
+        if b & 0x80 == 0 {
+            return Ok((n, i + 1));
+        }
+    }
+    unreachable!()
+}
+
+// Splits a schema definition into tokens, keeping quoted identifiers and parenthesized groups whole.
+// Not using sqlparser here since it doesn't accept the 'single quoted' identifiers used by the game's schema.
+fn tokenize(sql: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            quote @ (b'\'' | b'"' | b'`' | b'[') => {
+                let end_quote = if quote == b'[' { b']' } else { quote };
+                i += 1;
+                while i < bytes.len() && bytes[i] != end_quote {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'(' | b')' | b',' => i += 1,
+            _ => {
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !b"(),'\"`[".contains(&bytes[i]) {
+                    i += 1;
+                }
+            }
+        }
+        tokens.push(&sql[start..i.min(sql.len())]);
+    }
+    tokens
+}
+
+fn unquote(token: &str) -> &str {
+    let bytes = token.as_bytes();
+    match (bytes.first(), bytes.last()) {
+        (Some(b'\''), Some(b'\'')) | (Some(b'"'), Some(b'"')) | (Some(b'`'), Some(b'`')) | (Some(b'['), Some(b']'))
+            if token.len() >= 2 =>
+        {
+            &token[1..token.len() - 1]
+        }
+        _ => token,
+    }
+}
+
+fn parse_create_table(sql: &str, root_page: u32) -> Result<Table, Error> {
+    let tokens = tokenize(sql);
+    let Some(open) = tokens.iter().position(|t| *t == "(") else {
+        return Err(format_error("table schema has no column list"));
+    };
+
+    // Split the column list into its top level definitions
+    let mut definitions: Vec<Vec<&str>> = vec![Vec::new()];
+    let mut depth = 0;
+    let mut close = None;
+    for (i, token) in tokens.iter().enumerate().skip(open + 1) {
+        match *token {
+            "(" => depth += 1,
+            ")" if depth == 0 => {
+                close = Some(i);
+                break;
+            }
+            ")" => depth -= 1,
+            "," if depth == 0 => {
+                definitions.push(Vec::new());
+                continue;
+            }
+            _ => (),
+        }
+        definitions.last_mut().unwrap().push(token);
+    }
+    let Some(close) = close else {
+        return Err(format_error("unterminated column list in table schema"));
+    };
+    if tokens[close + 1..].iter().any(|t| t.eq_ignore_ascii_case("ROWID")) {
+        return Err(Error::RuntimeError("WITHOUT ROWID tables are not supported".to_owned()));
+    }
+
+    let is_keyword = |token: &str, keyword: &str| token.eq_ignore_ascii_case(keyword);
+    let mut columns = Vec::new();
+    let mut primary_key: Vec<String> = Vec::new();
+    let mut integer_columns = Vec::new();
+    for definition in definitions {
+        let Some(first) = definition.first() else {
+            continue;
+        };
+
+        if ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"].iter().any(|k| is_keyword(first, k)) {
+            // Table constraint, only the primary key matters
+            if let Some(pos) = definition.iter().position(|t| is_keyword(t, "PRIMARY")) {
+                primary_key.extend(
+                    definition[pos..]
+                        .iter()
+                        .skip_while(|t| **t != "(")
+                        .take_while(|t| **t != ")")
+                        .filter(|t| **t != "(")
+                        .map(|t| unquote(t).to_owned()),
+                );
+            }
+            continue;
+        }
+
+        let name = unquote(first).to_owned();
+        if definition.iter().any(|t| is_keyword(t, "PRIMARY")) {
+            primary_key.push(name.clone());
+        }
+        if definition.get(1).is_some_and(|t| is_keyword(t, "INTEGER")) {
+            integer_columns.push(name.clone());
+        }
+        columns.push(name);
+    }
+
+    // A single INTEGER PRIMARY KEY column is an alias for the rowid
+    let rowid_column = match primary_key.as_slice() {
+        [name] if integer_columns.contains(name) => columns.iter().position(|c| c == name),
+        _ => None,
+    };
+
+    Ok(Table { columns, root_page, rowid_column })
+}
@@ -0,0 +1,231 @@
+//! Extracts dict skeletons ({"key": "original text"}) from a copy of master.mdb, along with
+//! a diff against the existing dict so that translators can see what changed in an update.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::Serialize;
+
+use super::{
+    hachimi::LocalizedDataConfig,
+    master_db::MasterDb,
+    sql_table::SqlTableConfig,
+    utils, Error,
+};
+
+pub const DEFAULT_OUT_DIR: &str = "master_extract";
+pub const DIFF_SUFFIX: &str = ".diff.json";
+
+// Tables handled by the builtin query handlers: (table, text column, key columns, default dict)
+const BUILTIN_TABLES: &[(&str, &str, &[&str], &str)] = &[
+    ("text_data", "text", &["category", "index"], "text_data_dict.json"),
+    ("character_system_text", "text", &["character_id", "voice_id"], "character_system_text_dict.json"),
+    ("race_jikkyo_comment", "message", &["id"], "race_jikkyo_comment_dict.json"),
+    ("race_jikkyo_message", "message", &["id"], "race_jikkyo_message_dict.json"),
+];
+
+/// Tables to extract for a localized data config, using its dict paths where they're set.
+pub fn tables_for(config: &LocalizedDataConfig) -> Vec<SqlTableConfig> {
+    let mut tables: Vec<SqlTableConfig> = BUILTIN_TABLES
+        .iter()
+        .map(|(table, text_column, key_columns, default_dict)| {
+            let dict = match *table {
+                "text_data" => &config.text_data_dict,
+                "character_system_text" => &config.character_system_text_dict,
+                "race_jikkyo_comment" => &config.race_jikkyo_comment_dict,
+                "race_jikkyo_message" => &config.race_jikkyo_message_dict,
+                _ => &None,
+            };
+            SqlTableConfig {
+                table: table.to_string(),
+                text_column: text_column.to_string(),
+                key_columns: key_columns.iter().map(|c| c.to_string()).collect(),
+                dict: dict.clone().unwrap_or_else(|| default_dict.to_string()),
+            }
+        })
+        .collect();
+
+    // Config tables never shadow the builtin ones, see LocalizedData::load_config
+    tables.extend(config.sql_tables.iter().cloned());
+    tables
+}
+
+/// Builds a skeleton nested by the key columns, same as the table's dict.
+pub fn build_skeleton(db: &mut MasterDb, config: &SqlTableConfig) -> Result<serde_json::Value, Error> {
+    let table = db.table(&config.table)?;
+    let column_index = |name: &str| {
+        table
+            .column_index(name)
+            .ok_or_else(|| Error::RuntimeError(format!("Column not found: {}.{}", config.table, name)))
+    };
+    let text_idx = column_index(&config.text_column)?;
+    let key_idxs = config.key_columns.iter().map(|c| column_index(c)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut root = serde_json::Map::new();
+    db.scan(&table, |row| {
+        let Some(text) = row[text_idx].to_key() else {
+            return;
+        };
+        let Some(keys) = key_idxs.iter().map(|i| row[*i].to_key()).collect::<Option<Vec<_>>>() else {
+            return;
+        };
+        let Some((last_key, parent_keys)) = keys.split_last() else {
+            return;
+        };
+
+        let mut map = &mut root;
+        for key in parent_keys {
+            let node = map
+                .entry(key.as_str())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+            let serde_json::Value::Object(child) = node else {
+                return;
+            };
+            map = child;
+        }
+        map.insert(last_key.clone(), serde_json::Value::String(text));
+    })?;
+
+    Ok(serde_json::Value::Object(root))
+}
+
+fn flatten(value: &serde_json::Value, path: &mut Vec<String>, out: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::String(text) => {
+            out.insert(path.join("/"), text.clone());
+        }
+        serde_json::Value::Object(map) => {
+            for (key, child) in map {
+                path.push(key.clone());
+                flatten(child, path, out);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct ChangedText {
+    pub old: String,
+    pub new: String,
+}
+
+/// Entries are keyed by their key path, e.g. "47/1" for text_data.
+#[derive(Serialize, Default)]
+pub struct SkeletonDiff {
+    // Source text of keys that are missing from the dict
+    pub added: BTreeMap<String, String>,
+    // Translated text of keys that no longer exist in the database
+    pub removed: BTreeMap<String, String>,
+    // Translated keys whose source text changed since the previous extraction
+    pub changed: BTreeMap<String, ChangedText>,
+}
+
+/// Compares a skeleton against the existing dict and the skeleton from the previous extraction.
+///
+/// Added and removed keys come from the dict, changed source text from the previous skeleton
+/// since the dict only contains translations.
+pub fn diff(dict: &serde_json::Value, snapshot: &serde_json::Value, new: &serde_json::Value) -> SkeletonDiff {
+    let mut dict_entries = BTreeMap::new();
+    flatten(dict, &mut Vec::new(), &mut dict_entries);
+    let mut snapshot_entries = BTreeMap::new();
+    flatten(snapshot, &mut Vec::new(), &mut snapshot_entries);
+    let mut new_entries = BTreeMap::new();
+    flatten(new, &mut Vec::new(), &mut new_entries);
+
+    let mut res = SkeletonDiff::default();
+    for (key, new_text) in &new_entries {
+        if dict_entries.remove(key).is_none() {
+            res.added.insert(key.clone(), new_text.clone());
+            continue;
+        }
+        match snapshot_entries.remove(key) {
+            Some(old_text) if old_text != *new_text => {
+                res.changed.insert(key.clone(), ChangedText { old: old_text, new: new_text.clone() });
+            }
+            _ => (),
+        }
+    }
+    res.removed = dict_entries;
+    res
+}
+
+fn read_json_or_null(path: &Path) -> Result<serde_json::Value, Error> {
+    match fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(serde_json::Value::Null),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct TableSummary {
+    pub table: String,
+    pub entries: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+/// Writes the skeleton of each table to `out_dir`, at the same relative path as its dict.
+///
+/// Each skeleton is compared against the dict at the same path in `dict_dir` (usually the localized
+/// data dir) and against the previous skeleton in `out_dir`, and the diff is written next to it.
+/// Tables that are missing from the database or have unreadable dicts are skipped.
+pub fn extract(
+    db_path: &Path,
+    tables: &[SqlTableConfig],
+    out_dir: &Path,
+    dict_dir: Option<&Path>,
+) -> Result<Vec<TableSummary>, Error> {
+    let mut db = MasterDb::open(db_path)?;
+
+    let mut summaries = Vec::new();
+    for config in tables {
+        let skeleton = match build_skeleton(&mut db, config) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Skipping table {}: {}", config.table, e);
+                continue;
+            }
+        };
+
+        let out_path = out_dir.join(&config.dict);
+        let dict_path = dict_dir.map(|dir| dir.join(&config.dict));
+        let dict = match dict_path.as_deref().map(read_json_or_null).transpose() {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => {
+                warn!("Skipping table {}: failed to read dict: {}", config.table, e);
+                continue;
+            }
+        };
+        let snapshot = match read_json_or_null(&out_path) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Skipping table {}: failed to read previous skeleton: {}", config.table, e);
+                continue;
+            }
+        };
+        let diff = diff(&dict, &snapshot, &skeleton);
+
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut diff_path = out_path.clone().into_os_string();
+        diff_path.push(DIFF_SUFFIX);
+        utils::write_json_file(&skeleton, &out_path)?;
+        utils::write_json_file(&diff, diff_path)?;
+
+        let mut entries = BTreeMap::new();
+        flatten(&skeleton, &mut Vec::new(), &mut entries);
+        summaries.push(TableSummary {
+            table: config.table.clone(),
+            entries: entries.len(),
+            added: diff.added.len(),
+            removed: diff.removed.len(),
+            changed: diff.changed.len(),
+        });
+    }
+
+    Ok(summaries)
+}
@@ -12,6 +12,18 @@ pub mod gui;
 pub use gui::Gui;
 
 pub mod plurals;
+pub mod cldr_plurals;
+mod dict_cache;
+pub mod asset_dict_cache;
+pub mod load_report;
+pub mod po;
+pub mod xliff;
+pub mod coverage;
+pub mod hashed_dict;
+pub mod sql_table;
+pub mod sql_capture;
+pub mod master_db;
+pub mod master_extract;
 mod template_filters;
 
 #[macro_use] pub mod interceptor;
@@ -22,6 +34,7 @@ pub mod http;
 pub mod tl_repo;
 pub mod log;
 mod ipc;
+mod ld_watcher;
 
 mod sugoi_client;
 pub use sugoi_client::SugoiClient;
@@ -1,8 +1,8 @@
-use std::ffi::{c_char, c_void, CStr};
+use std::{ffi::{c_char, c_void, CStr, CString}, sync::Arc};
 
 use crate::{core::{Hachimi, Interceptor}, il2cpp::{self, types::{il2cpp_array_size_t, FieldInfo, Il2CppArray, Il2CppClass, Il2CppImage, Il2CppObject, Il2CppThread, Il2CppTypeEnum, MethodInfo}}};
 
-const VERSION: i32 = 1;
+const VERSION: i32 = 2;
 
 pub type HachimiInitFn = extern "C" fn(vtable: *const Vtable, version: i32) -> InitResult;
 
@@ -207,6 +207,45 @@ unsafe extern "C" fn log(level: i32, target: *const c_char, message: *const c_ch
     log!(target: &target, level, "{}", message);
 }
 
+/// Template filter callback.
+///
+/// Receives the filter arguments as UTF-8 strings. Returns a UTF-8 string, or null if the
+/// filter failed. The returned string must remain valid until the callback is called again
+/// on the same thread; Hachimi copies it right away.
+pub type TemplateFilterFn = unsafe extern "C" fn(
+    args: *const *const c_char, args_count: usize, userdata: *mut c_void
+) -> *const c_char;
+
+unsafe extern "C" fn template_register_filter(
+    name: *const c_char, callback: TemplateFilterFn, userdata: *mut c_void
+) -> bool {
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return false;
+    };
+
+    let userdata = userdata as usize;
+    let filter_name = name.to_owned();
+    Hachimi::instance().template_parser.register_filter(name, Arc::new(move |args| {
+        let args_cstr = match args.iter().map(|arg| CString::new(arg.to_string())).collect::<Result<Vec<_>, _>>() {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Template filter {}: argument can't be passed to the plugin: {}", filter_name, e);
+                return None;
+            }
+        };
+        let args_ptr: Vec<*const c_char> = args_cstr.iter().map(|arg| arg.as_ptr()).collect();
+
+        let res = unsafe { callback(args_ptr.as_ptr(), args_ptr.len(), userdata as _) };
+        if res.is_null() {
+            return None;
+        }
+        Some(unsafe { CStr::from_ptr(res) }.to_string_lossy().into_owned())
+    }));
+
+    info!("Registered template filter: {}", name);
+    true
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Vtable {
@@ -275,6 +314,11 @@ pub struct Vtable {
     pub il2cpp_get_singleton_like_instance: unsafe extern "C" fn(class: *mut Il2CppClass) -> *mut Il2CppObject,
 
     pub log: unsafe extern "C" fn(level: i32, target: *const c_char, message: *const c_char),
+
+    // Version 2
+    pub template_register_filter: unsafe extern "C" fn(
+        name: *const c_char, callback: TemplateFilterFn, userdata: *mut c_void
+    ) -> bool,
 }
 
 impl Vtable {
@@ -307,6 +351,7 @@ impl Vtable {
         il2cpp_create_array,
         il2cpp_get_singleton_like_instance,
         log,
+        template_register_filter,
     };
 
     pub fn instantiate() -> Self {
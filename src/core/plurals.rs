@@ -1,7 +1,9 @@
 /*
     gettext plural forms expression parser / resolver
     Modified from https://github.com/justinas/gettext
-    Limitation: It has no concept of operator precedence, plz use parentheses
+    Accepts the C expression grammar used by gettext's Plural-Forms header,
+    with the usual C operator precedence. Evaluation uses checked unsigned
+    arithmetic and returns an error on overflow/underflow or division by zero.
 
     The MIT License (MIT)
 
@@ -26,7 +28,7 @@
     SOFTWARE.
 */
 
-use super::Error;
+use super::{cldr_plurals, Error};
 use self::Resolver::*;
 
 #[derive(Clone, Debug)]
@@ -36,6 +38,9 @@ pub enum Resolver {
     Expr(Ast),
     /// A function
     Function(fn(u64) -> usize),
+    /// Built-in CLDR rules
+    /// Use cldr_plurals::Rules::cardinal or cldr_plurals::Rules::ordinal
+    Cldr(cldr_plurals::Rules),
 }
 
 impl Default for Resolver {
@@ -44,37 +49,6 @@ impl Default for Resolver {
     }
 }
 
-/// Finds the index of a pattern, outside of parenthesis
-fn index_of(src: &str, pat: &str) -> Option<usize> {
-    src.chars()
-        .fold(
-            (None, 0, 0, 0),
-            |(match_index, i, n_matches, paren_level), ch| {
-                if let Some(x) = match_index {
-                    (Some(x), i, n_matches, paren_level)
-                } else {
-                    let new_par_lvl = match ch {
-                        '(' => paren_level + 1,
-                        ')' => paren_level - 1,
-                        _ => paren_level,
-                    };
-
-                    if Some(ch) == pat.chars().nth(n_matches) {
-                        let length = n_matches + 1;
-                        if length == pat.len() && new_par_lvl == 0 {
-                            (Some(i - n_matches), i + 1, length, new_par_lvl)
-                        } else {
-                            (match_index, i + 1, length, new_par_lvl)
-                        }
-                    } else {
-                        (match_index, i + 1, 0, new_par_lvl)
-                    }
-                }
-            },
-        )
-        .0
-}
-
 use self::Ast::*;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Ast {
@@ -93,7 +67,7 @@ pub enum Ast {
     Not(Box<Ast>),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Operator {
     Equal,
     NotEqual,
@@ -110,270 +84,239 @@ pub enum Operator {
     Modulo
 }
 
-impl Ast {
-    fn resolve(&self, n: u64) -> usize {
-        match *self {
-            Ternary(ref cond, ref ok, ref nok) => {
-                if cond.resolve(n) == 0 {
-                    nok.resolve(n)
-                } else {
-                    ok.resolve(n)
-                }
-            }
-            N => n as usize,
-            Integer(x) => x as usize,
-            Op(ref op, ref lhs, ref rhs) => match *op {
-                Operator::Equal => (lhs.resolve(n) == rhs.resolve(n)) as usize,
-                Operator::NotEqual => (lhs.resolve(n) != rhs.resolve(n)) as usize,
-                Operator::GreaterOrEqual => (lhs.resolve(n) >= rhs.resolve(n)) as usize,
-                Operator::SmallerOrEqual => (lhs.resolve(n) <= rhs.resolve(n)) as usize,
-                Operator::Greater => (lhs.resolve(n) > rhs.resolve(n)) as usize,
-                Operator::Smaller => (lhs.resolve(n) < rhs.resolve(n)) as usize,
-                Operator::And => (lhs.resolve(n) != 0 && rhs.resolve(n) != 0) as usize,
-                Operator::Or => (lhs.resolve(n) != 0 || rhs.resolve(n) != 0) as usize,
-                Operator::Plus => lhs.resolve(n) + rhs.resolve(n),
-                Operator::Minus => lhs.resolve(n) - rhs.resolve(n),
-                Operator::Divide => lhs.resolve(n) / rhs.resolve(n),
-                Operator::Multiply => lhs.resolve(n) * rhs.resolve(n),
-                Operator::Modulo => lhs.resolve(n) % rhs.resolve(n),
-            },
-            Not(ref val) => match val.resolve(n) {
-                0 => 1,
-                _ => 0,
-            },
+impl Operator {
+    /// Binding power of the operator, higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::Or => 1,
+            Operator::And => 2,
+            Operator::Equal | Operator::NotEqual => 3,
+            Operator::GreaterOrEqual | Operator::SmallerOrEqual | Operator::Greater | Operator::Smaller => 4,
+            Operator::Plus | Operator::Minus => 5,
+            Operator::Divide | Operator::Multiply | Operator::Modulo => 6,
         }
     }
+}
 
-    pub fn parse(src: &str) -> Result<Ast, Error> {
-        Self::parse_parens(src.trim())
-    }
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    N,
+    Integer(u64),
+    Op(Operator),
+    Not,
+    Question,
+    Colon,
+    OpenParen,
+    CloseParen
+}
 
-    fn parse_parens(src: &str) -> Result<Ast, Error> {
-        if src.starts_with('(') {
-            let end = src[1..src.len() - 1]
-                .chars()
-                .fold((1, 2), |(level, index), ch| match (level, ch) {
-                    (0, '(') => (level + 1, index + 1),
-                    (0, _) => (level, index),
-                    (_, '(') => (level + 1, index + 1),
-                    (_, ')') => (level - 1, index + 1),
-                    (_, _) => (level, index + 1),
-                })
-                .1;
-            if end == src.len() {
-                Ast::parse(src[1..src.len() - 1].trim())
-            } else {
-                Ast::parse_and(src.trim())
+fn tokenize(src: &str) -> Result<Vec<Token>, Error> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        let (token, len) = match bytes[i] {
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                i += 1;
+                continue;
             }
-        } else {
-            Ast::parse_and(src.trim())
-        }
-    }
-
-    fn parse_and(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "&&") {
-            Ok(Ast::Op(
-                Operator::And,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 2..])?),
-            ))
-        } else {
-            Self::parse_or(src)
-        }
-    }
-
-    fn parse_or(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "||") {
-            Ok(Ast::Op(
-                Operator::Or,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 2..])?),
-            ))
-        } else {
-            Self::parse_ternary(src)
-        }
-    }
-
-    fn parse_ternary(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "?") {
-            if let Some(l) = index_of(src, ":") {
-                Ok(Ast::Ternary(
-                    Box::new(Ast::parse(&src[0..i])?),
-                    Box::new(Ast::parse(&src[i + 1..l])?),
-                    Box::new(Ast::parse(&src[l + 1..])?),
-                ))
-            } else {
-                Err(Error::PluralParsing)
+            b'0'..=b'9' => {
+                let start = i;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let value = src[start..i].parse::<u64>().map_err(|_| Error::PluralParsing)?;
+                tokens.push(Token::Integer(value));
+                continue;
             }
-        } else {
-            Self::parse_ge(src)
-        }
+            b'n' => (Token::N, 1),
+            b'?' => (Token::Question, 1),
+            b':' => (Token::Colon, 1),
+            b'(' => (Token::OpenParen, 1),
+            b')' => (Token::CloseParen, 1),
+            b'+' => (Token::Op(Operator::Plus), 1),
+            b'-' => (Token::Op(Operator::Minus), 1),
+            b'*' => (Token::Op(Operator::Multiply), 1),
+            b'/' => (Token::Op(Operator::Divide), 1),
+            b'%' => (Token::Op(Operator::Modulo), 1),
+            b'|' if next == Some(b'|') => (Token::Op(Operator::Or), 2),
+            b'&' if next == Some(b'&') => (Token::Op(Operator::And), 2),
+            b'=' if next == Some(b'=') => (Token::Op(Operator::Equal), 2),
+            b'!' if next == Some(b'=') => (Token::Op(Operator::NotEqual), 2),
+            b'!' => (Token::Not, 1),
+            b'>' if next == Some(b'=') => (Token::Op(Operator::GreaterOrEqual), 2),
+            b'>' => (Token::Op(Operator::Greater), 1),
+            b'<' if next == Some(b'=') => (Token::Op(Operator::SmallerOrEqual), 2),
+            b'<' => (Token::Op(Operator::Smaller), 1),
+            _ => return Err(Error::PluralParsing)
+        };
+        tokens.push(token);
+        i += len;
     }
 
-    fn parse_ge(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, ">=") {
-            Ok(Ast::Op(
-                Operator::GreaterOrEqual,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 2..])?),
-            ))
-        } else {
-            Self::parse_gt(src)
-        }
-    }
+    Ok(tokens)
+}
 
-    fn parse_gt(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, ">") {
-            Ok(Ast::Op(
-                Operator::Greater,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_le(src)
-        }
-    }
+/// Precedence climbing parser
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize
+}
 
-    fn parse_le(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "<=") {
-            Ok(Ast::Op(
-                Operator::SmallerOrEqual,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 2..])?),
-            ))
-        } else {
-            Self::parse_lt(src)
-        }
+impl Parser {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).copied()
     }
 
-    fn parse_lt(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "<") {
-            Ok(Ast::Op(
-                Operator::Smaller,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_eq(src)
-        }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
     }
 
-    fn parse_eq(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "==") {
-            Ok(Ast::Op(
-                Operator::Equal,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 2..])?),
-            ))
+    fn expect(&mut self, token: Token) -> Result<(), Error> {
+        if self.next() == Some(token) {
+            Ok(())
         } else {
-            Self::parse_neq(src)
+            Err(Error::PluralParsing)
         }
     }
 
-    fn parse_neq(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "!=") {
-            Ok(Ast::Op(
-                Operator::NotEqual,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 2..])?),
-            ))
-        } else {
-            Self::parse_plus(src)
+    // cond ? a : b (right associative, lowest precedence)
+    fn parse_ternary(&mut self) -> Result<Ast, Error> {
+        let cond = self.parse_binary(1)?;
+        if self.peek() != Some(Token::Question) {
+            return Ok(cond);
         }
-    }
+        self.next();
 
-    fn parse_plus(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "+") {
-            Ok(Ast::Op(
-                Operator::Plus,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_minus(src.trim())
-        }
+        let ok = self.parse_ternary()?;
+        self.expect(Token::Colon)?;
+        let nok = self.parse_ternary()?;
+        Ok(Ternary(Box::new(cond), Box::new(ok), Box::new(nok)))
     }
 
-    fn parse_minus(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "-") {
-            Ok(Ast::Op(
-                Operator::Minus,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_divide(src.trim())
-        }
-    }
+    // Left associative binary operators with a precedence of at least min_precedence
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Ast, Error> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.next();
 
-    fn parse_divide(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "/") {
-            Ok(Ast::Op(
-                Operator::Divide,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_multiply(src.trim())
+            let rhs = self.parse_binary(precedence + 1)?;
+            lhs = Op(op, Box::new(lhs), Box::new(rhs));
         }
+        Ok(lhs)
     }
 
-    fn parse_multiply(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "*") {
-            Ok(Ast::Op(
-                Operator::Multiply,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_mod(src.trim())
+    fn parse_unary(&mut self) -> Result<Ast, Error> {
+        match self.next() {
+            Some(Token::Not) => Ok(Not(Box::new(self.parse_unary()?))),
+            Some(Token::N) => Ok(N),
+            Some(Token::Integer(x)) => Ok(Integer(x)),
+            Some(Token::OpenParen) => {
+                let ast = self.parse_ternary()?;
+                self.expect(Token::CloseParen)?;
+                Ok(ast)
+            }
+            _ => Err(Error::PluralParsing)
         }
     }
+}
 
-    fn parse_mod(src: &str) -> Result<Ast, Error> {
-        if let Some(i) = index_of(src, "%") {
-            Ok(Ast::Op(
-                Operator::Modulo,
-                Box::new(Ast::parse(&src[0..i])?),
-                Box::new(Ast::parse(&src[i + 1..])?),
-            ))
-        } else {
-            Self::parse_not(src.trim())
-        }
-    }
+fn eval_error(msg: &str) -> Error {
+    Error::PluralEvaluation(msg.to_owned())
+}
 
-    fn parse_not(src: &str) -> Result<Ast, Error> {
-        if index_of(src, "!") == Some(0) {
-            Ok(Ast::Not(Box::new(Ast::parse(&src[1..])?)))
-        } else {
-            Self::parse_int(src.trim())
-        }
-    }
+impl Ast {
+    fn resolve(&self, n: u64) -> Result<u64, Error> {
+        Ok(match *self {
+            Ternary(ref cond, ref ok, ref nok) => {
+                if cond.resolve(n)? == 0 {
+                    nok.resolve(n)?
+                } else {
+                    ok.resolve(n)?
+                }
+            }
+            N => n,
+            Integer(x) => x,
+            Op(ref op, ref lhs, ref rhs) => {
+                // Short circuit like in C
+                match *op {
+                    Operator::And => return Ok((lhs.resolve(n)? != 0 && rhs.resolve(n)? != 0) as u64),
+                    Operator::Or => return Ok((lhs.resolve(n)? != 0 || rhs.resolve(n)? != 0) as u64),
+                    _ => {}
+                }
 
-    fn parse_int(src: &str) -> Result<Ast, Error> {
-        if let Ok(x) = u64::from_str_radix(src, 10) {
-            Ok(Ast::Integer(x))
-        } else {
-            Self::parse_n(src.trim())
-        }
+                let l = lhs.resolve(n)?;
+                let r = rhs.resolve(n)?;
+                match *op {
+                    Operator::Equal => (l == r) as u64,
+                    Operator::NotEqual => (l != r) as u64,
+                    Operator::GreaterOrEqual => (l >= r) as u64,
+                    Operator::SmallerOrEqual => (l <= r) as u64,
+                    Operator::Greater => (l > r) as u64,
+                    Operator::Smaller => (l < r) as u64,
+                    Operator::Plus => l.checked_add(r).ok_or_else(|| eval_error("addition overflow"))?,
+                    Operator::Minus => l.checked_sub(r).ok_or_else(|| eval_error("subtraction underflow"))?,
+                    Operator::Multiply => l.checked_mul(r).ok_or_else(|| eval_error("multiplication overflow"))?,
+                    Operator::Divide => l.checked_div(r).ok_or_else(|| eval_error("division by zero"))?,
+                    Operator::Modulo => l.checked_rem(r).ok_or_else(|| eval_error("modulo by zero"))?,
+                    Operator::And | Operator::Or => unreachable!()
+                }
+            }
+            Not(ref val) => (val.resolve(n)? == 0) as u64,
+        })
     }
 
-    fn parse_n(src: &str) -> Result<Ast, Error> {
-        if src == "n" {
-            Ok(Ast::N)
-        } else {
-            Err(Error::PluralParsing)
+    /// Parses a plural expression.
+    ///
+    /// Also accepts a full Plural-Forms header value, e.g. "nplurals=2; plural=(n != 1);"
+    pub fn parse(src: &str) -> Result<Ast, Error> {
+        let mut src = src.trim();
+        if let Some(i) = src.find("plural=") {
+            src = &src[i + 7..];
         }
+        let src = src.trim_end().trim_end_matches(';');
+
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0
+        };
+        let ast = parser.parse_ternary()?;
+        if parser.pos != parser.tokens.len() {
+            // Trailing tokens
+            return Err(Error::PluralParsing);
+        }
+        Ok(ast)
     }
 }
 
 impl Resolver {
     /// Returns the number of the correct plural form
     /// for `n` objects, as defined by the rule contained in this resolver.
-    pub fn resolve(&self, n: u64) -> usize {
+    pub fn resolve(&self, n: u64) -> Result<usize, Error> {
+        match *self {
+            Expr(ref ast) => Ok(ast.resolve(n)? as usize),
+            Function(ref f) => Ok(f(n)),
+            Cldr(ref rules) => Ok(rules.resolve(&cldr_plurals::Operands::from_u64(n))),
+        }
+    }
+
+    /// Same as resolve, but fractional numbers are supported by the CLDR rules.
+    /// gettext expressions and functions only get the integer part.
+    ///
+    /// `fraction_digits` is the number of visible fraction digits, since CLDR rules
+    /// tell "1" and "1.0" apart.
+    pub fn resolve_number(&self, n: f64, fraction_digits: usize) -> Result<usize, Error> {
         match *self {
-            Expr(ref ast) => ast.resolve(n),
-            Function(ref f) => f(n),
+            Cldr(ref rules) => Ok(rules.resolve(&cldr_plurals::Operands::from_f64(n, fraction_digits))),
+            _ => self.resolve(n.abs() as u64),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,536 @@
+//! gettext PO import/export for the localize, text_data and character_system_text dicts.
+//!
+//! Each dict entry becomes a PO entry with a stable msgctxt:
+//! - `localize|<TextId name>`
+//! - `text_data|<category>|<index>`
+//! - `character_system_text|<character_id>|<voice_id>`
+//!
+//! The msgid is the original text, entries whose original text isn't known are not exported
+//! (the dicts keep them as they are). Translations that consist of a single
+//! `$(plural n 'form' ...)` expression are exported as plural entries, with the `n` argument
+//! kept in a `hachimi-plural:` extracted comment so that the expression can be rebuilt on import.
+
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+use fnv::FnvHashMap;
+use serde::Serialize;
+
+use super::{
+    hachimi::{DictKind, LocalizedData, LocalizedDataConfig},
+    master_db::MasterDb,
+    master_extract, plurals, Error,
+};
+
+const PLURAL_COMMENT_PREFIX: &str = "hachimi-plural: ";
+
+#[derive(Default, Clone, Debug)]
+pub struct PoEntry {
+    pub extracted_comments: Vec<String>,
+    pub fuzzy: bool,
+    pub msgctxt: Option<String>,
+    pub msgid: String,
+    pub msgid_plural: Option<String>,
+    pub msgstr: Vec<String>,
+}
+
+/// Original (untranslated) texts, keyed the same way as the dicts.
+#[derive(Default)]
+pub struct Originals {
+    pub localize: BTreeMap<String, String>,
+    pub text_data: FnvHashMap<i32, FnvHashMap<i32, String>>,
+    pub character_system_text: FnvHashMap<i32, FnvHashMap<i32, String>>,
+}
+
+impl Originals {
+    /// Reads the text_data and character_system_text originals from a copy of master.mdb.
+    /// They take precedence over the ones that are already set (e.g. from old dumps).
+    pub fn fill_from_master_db(&mut self, path: &Path, config: &LocalizedDataConfig) -> Result<(), Error> {
+        let mut db = MasterDb::open(path)?;
+        for table in master_extract::tables_for(config) {
+            let originals = match table.table.as_str() {
+                "text_data" => &mut self.text_data,
+                "character_system_text" => &mut self.character_system_text,
+                _ => continue,
+            };
+            let skeleton = master_extract::build_skeleton(&mut db, &table)?;
+            let texts: FnvHashMap<i32, FnvHashMap<i32, String>> = serde_json::from_value(skeleton)?;
+            for (k1, category) in texts {
+                originals.entry(k1).or_default().extend(category);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct ImportedDicts {
+    pub localize: FnvHashMap<String, String>,
+    pub text_data: FnvHashMap<i32, FnvHashMap<i32, String>>,
+    pub character_system_text: FnvHashMap<i32, FnvHashMap<i32, String>>,
+    pub skipped: usize,
+}
+
+impl ImportedDicts {
+    pub fn len(&self) -> usize {
+        self.localize.len()
+            + self.text_data.values().map(|c| c.len()).sum::<usize>()
+            + self.character_system_text.values().map(|c| c.len()).sum::<usize>()
+    }
+}
+
+/// Builds the PO entries for all of the supported dicts.
+pub fn export_entries(ld: &LocalizedData, originals: &Originals) -> Vec<PoEntry> {
+    let mut entries = Vec::new();
+
+    let mut names: Vec<&String> = originals.localize.keys().chain(ld.localize_dict.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        entries.extend(make_entry(
+            format!("localize|{}", name),
+            originals.localize.get(name),
+            ld.localize_dict.get(name),
+        ));
+    }
+
+    export_nested(&mut entries, "text_data", &originals.text_data, &ld.text_data_dict);
+    export_nested(
+        &mut entries,
+        "character_system_text",
+        &originals.character_system_text,
+        &ld.character_system_text_dict,
+    );
+
+    entries
+}
+
+fn export_nested(
+    entries: &mut Vec<PoEntry>,
+    prefix: &str,
+    originals: &FnvHashMap<i32, FnvHashMap<i32, String>>,
+    dict: &FnvHashMap<i32, FnvHashMap<i32, String>>,
+) {
+    let mut keys: Vec<(i32, i32)> = originals
+        .iter()
+        .chain(dict.iter())
+        .flat_map(|(k1, c)| c.keys().map(move |k2| (*k1, *k2)))
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    for (k1, k2) in keys {
+        entries.extend(make_entry(
+            format!("{}|{}|{}", prefix, k1, k2),
+            originals.get(&k1).and_then(|c| c.get(&k2)),
+            dict.get(&k1).and_then(|c| c.get(&k2)),
+        ));
+    }
+}
+
+fn make_entry(msgctxt: String, original: Option<&String>, translation: Option<&String>) -> Option<PoEntry> {
+    // msgid can't be empty (reserved for the header)
+    let original = original.filter(|s| !s.is_empty())?;
+
+    let mut entry = PoEntry::default();
+    entry.msgid = original.clone();
+    entry.msgctxt = Some(msgctxt);
+
+    match translation.and_then(|t| parse_plural_expr(t)) {
+        Some((arg, forms)) => {
+            entry.extracted_comments.push(format!("{}{}", PLURAL_COMMENT_PREFIX, arg));
+            entry.msgid_plural = Some(entry.msgid.clone());
+            entry.msgstr = forms;
+        }
+        None => entry.msgstr = vec![translation.cloned().unwrap_or_default()],
+    }
+
+    Some(entry)
+}
+
+/// Parses a translation that consists of a single `$(plural n 'form' ...)` expression.
+fn parse_plural_expr(s: &str) -> Option<(String, Vec<String>)> {
+    let inner = s.strip_prefix("$(plural ")?.strip_suffix(')')?;
+    let (arg, mut rest) = inner.trim_start().split_once(' ')?;
+    if arg.starts_with('\'') {
+        return None;
+    }
+
+    let mut forms = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        let mut chars = rest.char_indices();
+        if chars.next()?.1 != '\'' {
+            return None;
+        }
+        let mut form = String::new();
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in chars {
+            if escaped {
+                if c != '\'' {
+                    form.push('\\');
+                }
+                form.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '\'' {
+                end = Some(i);
+                break;
+            } else {
+                form.push(c);
+            }
+        }
+        forms.push(form);
+        rest = &rest[end? + 1..];
+    }
+
+    if forms.is_empty() {
+        return None;
+    }
+    Some((arg.to_owned(), forms))
+}
+
+fn build_plural_expr(arg: &str, forms: &[String]) -> String {
+    let mut expr = format!("$(plural {}", arg);
+    for form in forms {
+        write!(expr, " '{}'", form.replace('\'', "\\'")).unwrap();
+    }
+    expr.push(')');
+    expr
+}
+
+/// Converts translated PO entries back to dicts. Fuzzy and untranslated entries are skipped.
+pub fn import_entries(entries: &[PoEntry]) -> ImportedDicts {
+    let mut dicts = ImportedDicts::default();
+
+    for entry in entries {
+        let Some(ctxt) = &entry.msgctxt else {
+            // Header or an entry that doesn't belong to us
+            if !entry.msgid.is_empty() {
+                dicts.skipped += 1;
+            }
+            continue;
+        };
+        if entry.fuzzy || entry.msgstr.iter().all(|s| s.is_empty()) {
+            dicts.skipped += 1;
+            continue;
+        }
+
+        let plural_arg = entry
+            .extracted_comments
+            .iter()
+            .find_map(|c| c.strip_prefix(PLURAL_COMMENT_PREFIX));
+        let text = match (&entry.msgid_plural, plural_arg) {
+            (Some(_), Some(arg)) => build_plural_expr(arg.trim(), &entry.msgstr),
+            _ => entry.msgstr[0].clone(),
+        };
+
+        let mut parts = ctxt.split('|');
+        let inserted = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("localize"), Some(name), None, None) => {
+                dicts.localize.insert(name.to_owned(), text);
+                true
+            }
+            (Some(prefix @ ("text_data" | "character_system_text")), Some(k1), Some(k2), None) => {
+                match (k1.parse::<i32>(), k2.parse::<i32>()) {
+                    (Ok(k1), Ok(k2)) => {
+                        let dict = if prefix == "text_data" {
+                            &mut dicts.text_data
+                        } else {
+                            &mut dicts.character_system_text
+                        };
+                        dict.entry(k1).or_default().insert(k2, text);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+        if !inserted {
+            dicts.skipped += 1;
+        }
+    }
+
+    dicts
+}
+
+fn write_string(out: &mut String, keyword: &str, value: &str) {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r");
+
+    if !escaped.contains('\n') {
+        writeln!(out, "{} \"{}\"", keyword, escaped).unwrap();
+        return;
+    }
+
+    // Multiline strings start with an empty string, one line per segment
+    writeln!(out, "{} \"\"", keyword).unwrap();
+    for line in escaped.split_inclusive('\n') {
+        writeln!(out, "\"{}\"", line.replace('\n', "\\n")).unwrap();
+    }
+}
+
+pub fn write_po(entries: &[PoEntry], plural_forms: Option<&str>) -> String {
+    let mut out = String::new();
+
+    // Header
+    out.push_str("msgid \"\"\nmsgstr \"\"\n");
+    out.push_str("\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    if let Some(plural_forms) = plural_forms {
+        writeln!(out, "\"Plural-Forms: {}\\n\"", plural_forms.replace('"', "\\\"")).unwrap();
+    }
+
+    for entry in entries {
+        out.push('\n');
+        for comment in entry.extracted_comments.iter() {
+            writeln!(out, "#. {}", comment).unwrap();
+        }
+        if entry.fuzzy {
+            out.push_str("#, fuzzy\n");
+        }
+        if let Some(msgctxt) = &entry.msgctxt {
+            write_string(&mut out, "msgctxt", msgctxt);
+        }
+        write_string(&mut out, "msgid", &entry.msgid);
+        if let Some(msgid_plural) = &entry.msgid_plural {
+            write_string(&mut out, "msgid_plural", msgid_plural);
+            for (i, msgstr) in entry.msgstr.iter().enumerate() {
+                write_string(&mut out, &format!("msgstr[{}]", i), msgstr);
+            }
+        } else {
+            write_string(&mut out, "msgstr", entry.msgstr.first().map(|s| s.as_str()).unwrap_or(""));
+        }
+    }
+
+    out
+}
+
+fn unescape(s: &str, line_num: usize) -> Result<String, Error> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| parse_error(line_num, "expected a quoted string"))?;
+
+    let mut res = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('r') => res.push('\r'),
+            Some(c @ ('\\' | '"')) => res.push(c),
+            _ => return Err(parse_error(line_num, "invalid escape sequence")),
+        }
+    }
+    Ok(res)
+}
+
+fn parse_error(line_num: usize, msg: &str) -> Error {
+    Error::RuntimeError(format!("PO parse error at line {}: {}", line_num, msg))
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Msgctxt,
+    Msgid,
+    MsgidPlural,
+    Msgstr(usize),
+}
+
+pub fn parse_po(input: &str) -> Result<Vec<PoEntry>, Error> {
+    let mut entries = Vec::new();
+    let mut entry = PoEntry::default();
+    let mut field: Option<Field> = None;
+    let mut has_content = false;
+
+    fn finish(entries: &mut Vec<PoEntry>, entry: &mut PoEntry, has_content: &mut bool) {
+        if *has_content {
+            entries.push(std::mem::take(entry));
+        }
+        *entry = PoEntry::default();
+        *has_content = false;
+    }
+
+    for (i, line) in input.lines().enumerate() {
+        let line_num = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            finish(&mut entries, &mut entry, &mut has_content);
+            field = None;
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            // A comment after the strings starts a new entry
+            if field.is_some() {
+                finish(&mut entries, &mut entry, &mut has_content);
+                field = None;
+            }
+            if let Some(c) = comment.strip_prefix('.') {
+                entry.extracted_comments.push(c.trim().to_owned());
+            } else if let Some(flags) = comment.strip_prefix(',') {
+                if flags.split(',').any(|f| f.trim() == "fuzzy") {
+                    entry.fuzzy = true;
+                }
+            }
+            // Obsolete entries (#~) and other comments are ignored
+            continue;
+        }
+
+        if line.starts_with('"') {
+            let value = unescape(line, line_num)?;
+            let target = match field {
+                Some(Field::Msgctxt) => entry.msgctxt.get_or_insert_with(String::new),
+                Some(Field::Msgid) => &mut entry.msgid,
+                Some(Field::MsgidPlural) => entry.msgid_plural.get_or_insert_with(String::new),
+                Some(Field::Msgstr(n)) => &mut entry.msgstr[n],
+                None => return Err(parse_error(line_num, "unexpected string")),
+            };
+            target.push_str(&value);
+            continue;
+        }
+
+        let (keyword, value) = line
+            .split_once(|c: char| c.is_ascii_whitespace())
+            .ok_or_else(|| parse_error(line_num, "expected a keyword followed by a string"))?;
+        let value = unescape(value.trim(), line_num)?;
+
+        let new_field = match keyword {
+            "msgctxt" => Field::Msgctxt,
+            "msgid" => Field::Msgid,
+            "msgid_plural" => Field::MsgidPlural,
+            "msgstr" => Field::Msgstr(0),
+            _ => {
+                let n = keyword
+                    .strip_prefix("msgstr[")
+                    .and_then(|s| s.strip_suffix(']'))
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .ok_or_else(|| parse_error(line_num, "unknown keyword"))?;
+                Field::Msgstr(n)
+            }
+        };
+
+        // Entries without a blank line in between
+        if matches!(new_field, Field::Msgctxt | Field::Msgid)
+            && matches!(field, Some(Field::Msgstr(_)))
+        {
+            finish(&mut entries, &mut entry, &mut has_content);
+        }
+
+        match new_field {
+            Field::Msgctxt => entry.msgctxt = Some(value),
+            Field::Msgid => entry.msgid = value,
+            Field::MsgidPlural => entry.msgid_plural = Some(value),
+            Field::Msgstr(n) => {
+                if entry.msgstr.len() <= n {
+                    entry.msgstr.resize(n + 1, String::new());
+                }
+                entry.msgstr[n] = value;
+            }
+        }
+        field = Some(new_field);
+        has_content = true;
+    }
+    finish(&mut entries, &mut entry, &mut has_content);
+
+    Ok(entries)
+}
+
+/// Writes the localized data (and the originals) to a PO file.
+pub fn export_to_file(ld: &LocalizedData, originals: &Originals, path: &Path) -> Result<usize, Error> {
+    let entries = export_entries(ld, originals);
+
+    fs::write(path, write_po(&entries, plural_forms_header(ld).as_deref()))?;
+
+    Ok(entries.len())
+}
+
+/// The header is left out if it can't be built, the entries are still usable without it.
+fn plural_forms_header(ld: &LocalizedData) -> Option<String> {
+    match (&ld.plural_form, &ld.config.plural_form) {
+        (plurals::Resolver::Cldr(rules), _) => Some(rules.to_gettext_plural_forms()),
+        (plurals::Resolver::Expr(_), Some(expr)) => {
+            // gettext expressions don't tell how many forms there are, count the ones that are used
+            let mut nplurals = 1;
+            for n in 0..1000 {
+                match ld.plural_form.resolve(n) {
+                    Ok(i) => nplurals = nplurals.max(i + 1),
+                    Err(e) => {
+                        warn!("Omitting Plural-Forms header: {}", e);
+                        return None;
+                    }
+                }
+            }
+            Some(format!("nplurals={}; plural={};", nplurals, expr.trim()))
+        }
+        _ => None,
+    }
+}
+
+/// Imports a translated PO file, merging its entries into the dict files in the highest priority
+/// localized data dir (the user overrides dir, if there is one).
+pub fn import_from_file(ld: &LocalizedData, path: &Path) -> Result<ImportedDicts, Error> {
+    let entries = parse_po(&fs::read_to_string(path)?)?;
+    let dicts = import_entries(&entries);
+
+    merge_into_dict_file(ld, DictKind::Localize, &dicts.localize)?;
+    merge_into_dict_file(ld, DictKind::TextData, &dicts.text_data)?;
+    merge_into_dict_file(ld, DictKind::CharacterSystemText, &dicts.character_system_text)?;
+
+    Ok(dicts)
+}
+
+fn merge_into_dict_file<K, V>(ld: &LocalizedData, kind: DictKind, dict: &FnvHashMap<K, V>) -> Result<(), Error>
+where
+    K: Serialize + ToString,
+    V: Serialize,
+{
+    if dict.is_empty() {
+        return Ok(());
+    }
+    let Some(path) = ld.get_dict_write_path(kind) else {
+        warn!("No {:?} dict configured, skipping {} imported entries", kind, dict.len());
+        return Ok(());
+    };
+
+    let mut value: serde_json::Value = if path.is_file() {
+        serde_json::from_str(&fs::read_to_string(&path)?)?
+    } else {
+        serde_json::Value::Object(Default::default())
+    };
+    let serde_json::Value::Object(map) = &mut value else {
+        return Err(Error::RuntimeError(format!("'{}' is not a JSON object", path.display())));
+    };
+
+    for (k, v) in dict {
+        let new_value = serde_json::to_value(v)?;
+        match (map.get_mut(&k.to_string()), new_value) {
+            // Nested dicts are merged per key
+            (Some(serde_json::Value::Object(existing)), serde_json::Value::Object(new_map)) => {
+                existing.extend(new_map);
+            }
+            (_, new_value) => {
+                map.insert(k.to_string(), new_value);
+            }
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    super::utils::write_json_file(&value, &path)
+}
@@ -0,0 +1,103 @@
+use std::{
+    fs,
+    sync::{
+        atomic::{self, AtomicBool},
+        Mutex, Once,
+    },
+    time::Duration,
+};
+
+use super::{utils, Hachimi};
+
+pub const FILENAME: &str = "sql_capture.json";
+
+// Captured text is written from a separate thread so that file IO doesn't land on the main thread
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Untranslated text returned by translated master.mdb tables, captured in translator mode.
+///
+/// Saved to `sql_capture.json` as `{"table": {"key1": {"key2": "text"}}}`, with the same nesting
+/// as the table's dict (e.g. text_data_dict), so that each section can be merged into the repo as is.
+/// Entries from previous sessions are kept.
+#[derive(Default)]
+pub struct SqlCapture {
+    entries: Mutex<serde_json::Map<String, serde_json::Value>>,
+    dirty: AtomicBool,
+    init: Once,
+}
+
+impl SqlCapture {
+    pub fn record(&self, table: &str, keys: &[String], text: String) {
+        // Called from a query hook, the previous capture is loaded by the save thread
+        self.init.call_once(|| {
+            std::thread::spawn(save_thread);
+        });
+
+        let Some((last_key, parent_keys)) = keys.split_last() else {
+            return;
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        let mut node = entries
+            .entry(table)
+            .or_insert_with(|| serde_json::Value::Object(Default::default()));
+        for key in parent_keys {
+            let serde_json::Value::Object(map) = node else {
+                return;
+            };
+            node = map
+                .entry(key.as_str())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+        }
+
+        let serde_json::Value::Object(map) = node else {
+            return;
+        };
+        if map.contains_key(last_key) {
+            return;
+        }
+        map.insert(last_key.clone(), serde_json::Value::String(text));
+        self.dirty.store(true, atomic::Ordering::Relaxed);
+    }
+
+    fn load_previous(&self) {
+        let path = Hachimi::instance().get_data_path(FILENAME);
+        let Ok(json) = fs::read_to_string(&path) else {
+            return;
+        };
+        let mut previous: serde_json::Value = match serde_json::from_str(&json) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Failed to read '{}', it will be overwritten: {}", path.display(), e);
+                return;
+            }
+        };
+
+        // Text captured in the meantime goes on top
+        let mut entries = self.entries.lock().unwrap();
+        utils::merge_json_value(&mut previous, serde_json::Value::Object(std::mem::take(&mut *entries)));
+        if let serde_json::Value::Object(map) = previous {
+            *entries = map;
+        }
+    }
+
+    fn save_if_dirty(&self) {
+        if !self.dirty.swap(false, atomic::Ordering::Relaxed) {
+            return;
+        }
+
+        let path = Hachimi::instance().get_data_path(FILENAME);
+        let entries = self.entries.lock().unwrap().clone();
+        if let Err(e) = utils::write_json_file(&entries, &path) {
+            error!("Failed to save '{}': {}", path.display(), e);
+        }
+    }
+}
+
+fn save_thread() {
+    Hachimi::instance().sql_capture.load_previous();
+    loop {
+        std::thread::sleep(SAVE_INTERVAL);
+        Hachimi::instance().sql_capture.save_if_dirty();
+    }
+}
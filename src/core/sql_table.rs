@@ -0,0 +1,93 @@
+use fnv::FnvHashMap;
+use serde::Deserialize;
+
+use super::{
+    dict_cache::{CacheValue, Reader},
+    load_report::DictLen,
+};
+
+/// Tables that have a builtin query handler, they can't be declared as sql tables.
+pub const BUILTIN_TABLES: &[&str] = &["text_data", "character_system_text", "race_jikkyo_comment", "race_jikkyo_message"];
+
+/// Declarative translation of a master.mdb table, for tables that don't have a builtin query handler.
+///
+/// e.g. `{"table": "chara_data", "text_column": "name", "key_columns": ["id"], "dict": "chara_data_dict.json"}`
+#[derive(Deserialize, Clone)]
+pub struct SqlTableConfig {
+    pub table: String,
+    pub text_column: String,
+    // Int columns that identify a row, either bound as WHERE params or selected along with the text
+    #[serde(default)]
+    pub key_columns: Vec<String>,
+    pub dict: String,
+}
+
+/// Dict nested by the values of the key columns, in the same order: {"key1": {"key2": "text"}}
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum SqlTableDict {
+    Text(String),
+    Nested(FnvHashMap<String, SqlTableDict>),
+}
+
+impl Default for SqlTableDict {
+    fn default() -> Self {
+        SqlTableDict::Nested(FnvHashMap::default())
+    }
+}
+
+impl SqlTableDict {
+    pub fn get<S: AsRef<str>>(&self, keys: &[S]) -> Option<&String> {
+        match (self, keys.split_first()) {
+            (SqlTableDict::Text(text), None) => Some(text),
+            (SqlTableDict::Nested(map), Some((key, rest))) => map.get(key.as_ref())?.get(rest),
+            _ => None,
+        }
+    }
+
+    pub fn fill_missing(&mut self, fallback: Option<SqlTableDict>) {
+        let (SqlTableDict::Nested(map), Some(SqlTableDict::Nested(fallback_map))) = (self, fallback) else {
+            return;
+        };
+        for (k, v) in fallback_map {
+            match map.get_mut(&k) {
+                Some(entry) => entry.fill_missing(Some(v)),
+                None => {
+                    map.insert(k, v);
+                }
+            }
+        }
+    }
+}
+
+impl DictLen for SqlTableDict {
+    fn entry_count(&self) -> usize {
+        match self {
+            SqlTableDict::Text(_) => 1,
+            SqlTableDict::Nested(map) => map.entry_count(),
+        }
+    }
+}
+
+impl CacheValue for SqlTableDict {
+    fn write(&self, buf: &mut Vec<u8>) {
+        match self {
+            SqlTableDict::Text(text) => {
+                buf.push(0);
+                text.write(buf);
+            }
+            SqlTableDict::Nested(map) => {
+                buf.push(1);
+                map.write(buf);
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(match reader.read_u8()? {
+            0 => SqlTableDict::Text(String::read(reader)?),
+            1 => SqlTableDict::Nested(FnvHashMap::read(reader)?),
+            _ => return None,
+        })
+    }
+}
@@ -4,22 +4,91 @@
     Syntax:
     - Filter: $(filter_name arg1 arg2 arg3 ...)
       Loosely based on Bash command substitution syntax.
+      Arguments can be identifiers, number literals, 'string literals',
+      variables or nested filter expressions.
+    - Nested filter: $(plural $(count) '$ item' '$ items')
+      Nested expressions are evaluated first (inside-out) and their result is
+      passed to the outer filter as a number if it looks like one, or as a string.
+      Numbers keep their text, so "007" is still "007" when it's displayed.
+    - Variable: $name (only valid inside of a filter expression)
+      Variables are provided by the evaluation context, or captured earlier in
+      the same string with $(set name value).
+    - Escape: $$( outputs a literal "$(" without starting an expression.
+      Expressions that don't start with a filter name (e.g. "$(5)") are also
+      left as-is. Text that gets evaluated again later (e.g. by TextGenerator)
+      should go through eval_with_context_escaped so that escapes still hold.
 
     More expression types might be added later, but the filter expression
     is already suitable for most if not all use cases.
+
+    Templates are compiled once into a list of text and expression nodes and
+    cached by their source string, so repeated evaluations (e.g. on every
+    layout pass of TextGenerator) do no tokenizing.
 */
-use fnv::FnvHashMap;
+use std::{collections::VecDeque, fmt, hash::{Hash, Hasher}, ops::Range, sync::{Arc, Mutex, RwLock}};
+
+use fnv::{FnvHashMap, FnvHasher};
 
+#[derive(Clone, Debug)]
 pub enum Token {
     Identifier(String),
-    NumberLit(f64),
+    /// The value along with its source text, which is what gets displayed
+    /// (e.g. "007" stays "007" when passed through a filter).
+    NumberLit { value: f64, text: String },
     StringLit(String)
 }
 
+impl Token {
+    /// Converts the result of a nested expression or a variable value to a token.
+    pub fn from_value(value: String) -> Token {
+        match value.parse::<f64>() {
+            // Don't pick up "inf" or "nan"
+            Ok(number) if number.is_finite() => Token::NumberLit { value: number, text: value },
+            _ => Token::StringLit(value)
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Token::NumberLit { value, .. } => Some(*value),
+            _ => None
+        }
+    }
+
+    /// Number of visible fraction digits (e.g. 1 for "1.0"), for plural rules.
+    pub fn fraction_digits(&self) -> usize {
+        let Token::NumberLit { value, text } = self else {
+            return 0;
+        };
+        match text.split_once('.') {
+            Some((_, frac_part)) if frac_part.bytes().all(|c| c.is_ascii_digit()) => frac_part.len(),
+            // Exponent notation, use the shortest representation instead
+            _ => value.to_string().split_once('.').map_or(0, |(_, frac_part)| frac_part.len())
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "{}", name),
+            Token::NumberLit { text, .. } => write!(f, "{}", text),
+            Token::StringLit(str) => write!(f, "{}", str)
+        }
+    }
+}
+
 pub type Filter = fn(args: &[Token]) -> Option<String>;
+/// Filter registered at runtime (e.g. by plugins).
+pub type DynFilter = Arc<dyn Fn(&[Token]) -> Option<String> + Send + Sync>;
 
 pub trait Context {
     fn on_filter_eval(&mut self, name: &str, args: &[Token]) -> Option<String>;
+
+    /// Resolves a named variable ($name) used as a filter argument.
+    fn get_variable(&mut self, _name: &str) -> Option<Token> {
+        None
+    }
 }
 
 struct EmptyContext();
@@ -38,8 +107,75 @@ impl Context for FilterRemovalContext {
     }
 }
 
+// Per-evaluation state
+struct EvalState<'a, C: Context> {
+    context: &'a mut C,
+    variables: FnvHashMap<String, Token>
+}
+
+impl<'a, C: Context> EvalState<'a, C> {
+    fn get_variable(&mut self, name: &str) -> Option<Token> {
+        if let Some(value) = self.variables.get(name) {
+            return Some(value.clone());
+        }
+        self.context.get_variable(name)
+    }
+}
+
+/// A compiled template.
+pub struct Template {
+    source: String,
+    nodes: Vec<Node>
+}
+
+impl Template {
+    /// Returns true if the template evaluates to its source string.
+    pub fn is_plain_text(&self) -> bool {
+        self.nodes.len() <= 1 && self.nodes.iter().all(|node| matches!(node, Node::Text(_)))
+    }
+}
+
+/// Returns true if an expression starts at `i`, i.e. there's a "$(" that isn't part of a "$$(" escape.
+pub fn is_expr_start(bytes: &[u8], i: usize) -> bool {
+    bytes.get(i) == Some(&b'$') && bytes.get(i + 1) == Some(&b'(') && (i == 0 || bytes[i - 1] != b'$')
+}
+
+enum Node {
+    Text(Range<usize>),
+    Expr(Expr),
+    // "$$(", the "(" is part of the following text
+    Escape
+}
+
+struct Expr {
+    /// Position of the whole expression in the source, used for the fallback output.
+    src: Range<usize>,
+    /// Tokens resolved at compile time. Dynamic args have a placeholder in their place.
+    tokens: Vec<Token>,
+    dynamic_args: Vec<(usize, DynamicArg)>
+}
+
+enum DynamicArg {
+    Variable(String),
+    Expr(Expr)
+}
+
+// The cache is split into shards so that threads evaluating templates don't contend on a single lock.
+// Each shard drops its oldest templates one at a time when full.
+const CACHE_SHARD_COUNT: usize = 16;
+const CACHE_SHARD_SIZE: usize = 256;
+
+#[derive(Default)]
+struct CacheShard {
+    templates: FnvHashMap<Arc<str>, Arc<Template>>,
+    insert_order: VecDeque<Arc<str>>
+}
+
 pub struct Parser {
-    filters: FnvHashMap<String, Filter>
+    filters: FnvHashMap<String, Filter>,
+    // Consulted before the builtin filters
+    dyn_filters: RwLock<FnvHashMap<String, DynFilter>>,
+    cache: [Mutex<CacheShard>; CACHE_SHARD_COUNT]
 }
 
 impl Parser {
@@ -49,20 +185,47 @@ impl Parser {
             filters.insert(name.to_string(), filter.to_owned());
         }
 
-        Parser { filters }
+        Parser {
+            filters,
+            dyn_filters: RwLock::default(),
+            cache: Default::default()
+        }
+    }
+
+    /// Registers a filter that takes priority over the builtin filters.
+    /// Replaces any previously registered filter with the same name.
+    pub fn register_filter(&self, name: &str, filter: DynFilter) {
+        self.dyn_filters.write().unwrap().insert(name.to_owned(), filter);
     }
 
-    fn eval_filter(&self, tokens: &Vec<Token>, context: &mut impl Context) -> Option<String> {
+    fn eval_filter<C: Context>(&self, tokens: &[Token], state: &mut EvalState<C>) -> Option<String> {
         if tokens.is_empty() { return None; }
 
         if let Token::Identifier(filter_name) = tokens.first().unwrap() {
-            let args = &tokens.as_slice()[1..];
-            let context_res = context.on_filter_eval(filter_name, args);
+            let args = &tokens[1..];
+
+            // $(set name value)
+            if filter_name == "set" {
+                let (Some(Token::Identifier(name)), Some(value), None) = (args.get(0), args.get(1), args.get(2)) else {
+                    return None;
+                };
+                state.variables.insert(name.clone(), value.clone());
+                return Some(String::new());
+            }
+
+            let context_res = state.context.on_filter_eval(filter_name, args);
             if context_res.is_some() {
                 return context_res
             }
+
+            // Don't hold the lock while evaluating filters, they might evaluate templates
+            // or register filters themselves
+            let dyn_filter = self.dyn_filters.read().unwrap().get(filter_name).cloned();
+            if let Some(filter) = dyn_filter {
+                return filter(args);
+            }
             else if let Some(filter) = self.filters.get(filter_name) {
-                return filter(&tokens.as_slice()[1..]);
+                return filter(args);
             }
         }
 
@@ -80,143 +243,286 @@ impl Parser {
 
         if start_char.is_numeric() {
             return if let Ok(number) = input.parse::<f64>() {
-                Some(Token::NumberLit(number))
+                Some(Token::NumberLit { value: number, text: input.to_owned() })
             }
             else if let Ok(number) = input.replace(",", "").parse::<f64>() {
                 // Allow commas
                 // (not doing in the initial parse; the idea being that numbers with commas are not common)
-                Some(Token::NumberLit(number))
+                Some(Token::NumberLit { value: number, text: input.to_owned() })
             }
             else {
                 None
             }
         }
 
-        if input.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        if Self::is_identifier(input) {
             return Some(Token::Identifier(input.to_owned()));
         }
 
         None
     }
 
-    pub fn eval(&self, input: &str) -> String {
-        self.eval_with_context(input, &mut EmptyContext {})
+    fn is_identifier(input: &str) -> bool {
+        !input.is_empty() && input.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
     }
 
-    pub fn eval_with_context(&self, input: &str, context: &mut impl Context) -> String {
-        let mut output: Vec<u8> = Vec::with_capacity(input.len());
-
-        let mut start_expr = false;
-        let mut in_filter = false;
-        let mut checkpoint: usize = 0;
+    /// Parses a filter expression. `start` is the position right after the "$(".
+    ///
+    /// Returns the position after the closing parenthesis along with the expression,
+    /// or None if the expression is malformed (unterminated or contains an invalid token).
+    fn parse_expr(input: &str, start: usize) -> Option<(usize, Expr)> {
+        let bytes = input.as_bytes();
         let mut tokens: Vec<Token> = Vec::new();
-        let mut token_start: usize = 0;
-        let mut in_string = false;
-        let mut start_escape = false;
+        let mut dynamic_args: Vec<(usize, DynamicArg)> = Vec::new();
+        let mut i = start;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b')' => {
+                    let expr = Expr {
+                        src: start - 2..i + 1,
+                        tokens,
+                        dynamic_args
+                    };
+                    return Some((i + 1, expr));
+                }
+
+                b' ' | b'\n' | b'\r' | b'\t' => i += 1,
+
+                b'\'' => {
+                    // String literal
+                    let token_start = i;
+                    i += 1;
+                    let mut start_escape = false;
+                    while i < bytes.len() {
+                        let c = bytes[i];
+                        i += 1;
+                        if start_escape {
+                            start_escape = false;
+                        }
+                        else if c == b'\\' {
+                            start_escape = true;
+                        }
+                        else if c == b'\'' {
+                            break;
+                        }
+                    }
+                    let Some(token) = Self::parse_token(&input[token_start..i]) else {
+                        warn!("Invalid token in '{}' (at pos {})", input, token_start);
+                        return None;
+                    };
+                    tokens.push(token);
+                }
+
+                b'$' if bytes.get(i + 1) == Some(&b'(') => {
+                    // Nested expression
+                    let (end, expr) = Self::parse_expr(input, i + 2)?;
+                    dynamic_args.push((tokens.len(), DynamicArg::Expr(expr)));
+                    tokens.push(Token::StringLit(String::new()));
+                    i = end;
+                }
+
+                _ => {
+                    let token_start = i;
+                    while i < bytes.len() && !matches!(bytes[i], b')' | b' ' | b'\n' | b'\r' | b'\t') {
+                        i += 1;
+                    }
+                    let token_str = &input[token_start..i];
+
+                    if let Some(name) = token_str.strip_prefix('$') {
+                        // Variable
+                        if !Self::is_identifier(name) {
+                            warn!("Invalid variable name in '{}' (at pos {})", input, token_start);
+                            return None;
+                        }
+                        dynamic_args.push((tokens.len(), DynamicArg::Variable(name.to_owned())));
+                        tokens.push(Token::StringLit(String::new()));
+                    }
+                    else if let Some(token) = Self::parse_token(token_str) {
+                        tokens.push(token);
+                    }
+                    else {
+                        warn!("Invalid token in '{}' (at pos {})", input, token_start);
+                        return None;
+                    }
+                }
+            }
+        }
+
+        // Unterminated expression
+        None
+    }
+
+    /// Compiles a template without going through the cache.
+    pub fn compile(input: &str) -> Template {
+        let mut nodes = Vec::new();
+        let bytes = input.as_bytes();
+        // Start of the pending plain text section
+        let mut text_start = 0;
+        let mut i = 0;
 
         // Iterate through the bytes directly for the sake of simplicity
         // (it's also faster than going through char())
         // A caveat is that the "syntax parsing" portion of the parser has
         // no knowledge of Unicode characters; it doesn't need to anyways, UTF-8
         // sequences do not conflict with normal ascii characters.
-        for (i, c) in input.bytes().enumerate() {
-            output.push(c);
+        while i < bytes.len() {
+            if bytes[i] == b'$' && bytes.get(i + 1) == Some(&b'$') && bytes.get(i + 2) == Some(&b'(') {
+                if text_start != i {
+                    nodes.push(Node::Text(text_start..i));
+                }
+                nodes.push(Node::Escape);
+                text_start = i + 2;
+                i += 3;
+                continue;
+            }
 
-            if in_filter {
-                // Continue if string char is escaped
-                if start_escape {
-                    start_escape = false;
+            if bytes[i] == b'$' && bytes.get(i + 1) == Some(&b'(') {
+                let res = Self::parse_expr(input, i + 2)
+                    .filter(|(_, expr)| matches!(expr.tokens.first(), Some(Token::Identifier(_))));
+                if let Some((end, expr)) = res {
+                    if text_start != i {
+                        nodes.push(Node::Text(text_start..i));
+                    }
+                    nodes.push(Node::Expr(expr));
+                    text_start = end;
+                    i = end;
                     continue;
                 }
+            }
+            i += 1;
+        }
+        if text_start != bytes.len() {
+            nodes.push(Node::Text(text_start..bytes.len()));
+        }
 
-                // Check separator and expr close
-                match c {
-                    b')' => 'filter_close: {
-                        if in_string { break 'filter_close; }
-
-                        // Parse token (if it hasnt been terminated by a trailing separator yet)
-                        if token_start != 0 {
-                            let res = Self::parse_token(&input[token_start..i]);
-                            if let Some(token) = res {
-                                tokens.push(token);
-                                token_start = 0;
-                            }
-                            else {
-                                warn!("Invalid token in '{}' (at pos {})", input, token_start);
-                                token_start = 0;
-                                tokens.clear();
-                                in_filter = false;
-                                break 'filter_close;
-                            }
-                        }
+        Template {
+            source: input.to_owned(),
+            nodes
+        }
+    }
 
-                        if let Some(res) = self.eval_filter(&tokens, context) {
-                            output.truncate(checkpoint);
-                            output.extend(res.bytes());
-                        }
-                        else {
-                            warn!("Filter evaluation failed in '{}' (at pos {})", input, i);
-                        }
+    /// Gets the compiled template from the cache, compiling it if needed.
+    pub fn get_template(&self, input: &str) -> Arc<Template> {
+        let mut hasher = FnvHasher::default();
+        input.hash(&mut hasher);
+        let mut shard = self.cache[hasher.finish() as usize % CACHE_SHARD_COUNT].lock().unwrap();
+        if let Some(template) = shard.templates.get(input) {
+            return template.clone();
+        }
 
-                        tokens.clear();
-                        in_filter = false;
-                    },
+        if shard.insert_order.len() >= CACHE_SHARD_SIZE {
+            if let Some(oldest) = shard.insert_order.pop_front() {
+                shard.templates.remove(&oldest);
+            }
+        }
+        let template = Arc::new(Self::compile(input));
+        let key: Arc<str> = Arc::from(input);
+        shard.templates.insert(key.clone(), template.clone());
+        shard.insert_order.push_back(key);
+        template
+    }
 
-                    b' ' | b'\n' | b'\r' | b'\t' => if !in_string && token_start != 0 {
-                        let res = Self::parse_token(&input[token_start..i]);
-                        if let Some(token) = res {
-                            tokens.push(token);
-                        }
-                        else {
-                            warn!("Invalid token in '{}' (at pos {})", input, token_start);
-                            tokens.clear();
-                            in_filter = false;
-                        }
-                        token_start = 0;
-                    },
+    pub fn cache_size(&self) -> usize {
+        self.cache.iter().map(|shard| shard.lock().unwrap().templates.len()).sum()
+    }
 
-                    b'\'' => {
-                        if token_start == 0 {
-                            token_start = i;
-                            in_string = true;
+    fn eval_expr<C: Context>(&self, template: &Template, expr: &Expr, state: &mut EvalState<C>) -> Option<String> {
+        let res = if expr.dynamic_args.is_empty() {
+            self.eval_filter(&expr.tokens, state)
+        }
+        else {
+            let mut tokens = expr.tokens.clone();
+            for (i, arg) in expr.dynamic_args.iter() {
+                tokens[*i] = match arg {
+                    DynamicArg::Variable(name) => {
+                        let Some(value) = state.get_variable(name) else {
+                            warn!("Undefined variable '{}' in '{}'", name, template.source);
+                            return None;
+                        };
+                        value
+                    }
+                    DynamicArg::Expr(nested) => Token::from_value(self.eval_expr(template, nested, state)?)
+                };
+            }
+            self.eval_filter(&tokens, state)
+        };
+
+        if res.is_none() {
+            warn!("Filter evaluation failed in '{}' (at pos {})", template.source, expr.src.end - 1);
+        }
+        res
+    }
+
+    pub fn eval_template(&self, template: &Template, context: &mut impl Context) -> String {
+        self.eval_template_impl(template, context, false)
+    }
+
+    fn eval_template_impl(&self, template: &Template, context: &mut impl Context, keep_escapes: bool) -> String {
+        let mut state = EvalState {
+            context,
+            variables: FnvHashMap::default()
+        };
+
+        let mut output = String::with_capacity(template.source.len());
+        for node in template.nodes.iter() {
+            match node {
+                Node::Text(range) => output.push_str(&template.source[range.clone()]),
+                Node::Escape => output.push_str(if keep_escapes { "$$" } else { "$" }),
+                Node::Expr(expr) => {
+                    if let Some(res) = self.eval_expr(template, expr, &mut state) {
+                        if keep_escapes {
+                            output.push_str(&res.replace("$(", "$$("));
                         }
                         else {
-                            in_string = false;
+                            output.push_str(&res);
                         }
                     }
-
-                    b'\\' => if in_string {
-                        start_escape = true;
-                    }
-
-                    _ => if token_start == 0 {
-                        token_start = i;
+                    else {
+                        // Keep the expression as-is
+                        output.push_str(&template.source[expr.src.clone()]);
                     }
                 }
-                continue;
             }
+        }
 
-            if start_expr {
-                // Check expression opening
-                if c == b'(' { // Filter expression
-                    in_filter = true;
-                }
-                start_expr = false;
-                continue;
-            }
+        output
+    }
 
-            // Check for expression start
-            if c == b'$' {
-                start_expr = true;
-                checkpoint = output.len() - 1; // before the starting char
-            }
+    pub fn eval(&self, input: &str) -> String {
+        self.eval_with_context(input, &mut EmptyContext {})
+    }
+
+    pub fn eval_with_context(&self, input: &str, context: &mut impl Context) -> String {
+        self.eval_with_context_impl(input, context, false)
+    }
+
+    /// Same as eval_with_context, but escapes are kept (and literal "$(" in filter results are escaped)
+    /// so that evaluating the result again outputs the same text. Expressions that failed to evaluate are
+    /// kept as-is, so they still get a chance later (e.g. TextGenerator filters).
+    pub fn eval_with_context_escaped(&self, input: &str, context: &mut impl Context) -> String {
+        self.eval_with_context_impl(input, context, true)
+    }
+
+    pub fn eval_escaped(&self, input: &str) -> String {
+        self.eval_with_context_escaped(input, &mut EmptyContext {})
+    }
+
+    fn eval_with_context_impl(&self, input: &str, context: &mut impl Context, keep_escapes: bool) -> String {
+        // Most strings have no expressions at all, don't bother with the cache for those
+        if !input.contains("$(") {
+            return input.to_owned();
         }
 
-        unsafe { String::from_utf8_unchecked(output) }
+        let template = self.get_template(input);
+        if template.is_plain_text() {
+            return input.to_owned();
+        }
+        self.eval_template_impl(&template, context, keep_escapes)
     }
 
     /// Evaluate the template with a context that returns an empty string on any filter expr
     pub fn remove_filters(&self, input: &str) -> String {
         self.eval_with_context(input, &mut FilterRemovalContext {})
     }
-}
\ No newline at end of file
+}
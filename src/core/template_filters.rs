@@ -1,21 +1,36 @@
-use super::{template, Hachimi};
+use chrono::{Datelike, NaiveDate, TimeDelta};
+use unicode_width::UnicodeWidthStr;
 
-pub static LIST: [(&str, template::Filter); 3] = [
+use super::{hachimi::{LocalizedData, NumberFormat}, template, Hachimi};
+
+pub static LIST: [(&str, template::Filter); 13] = [
     ("plural", plural),
     ("ordinal", ordinal),
-    ("month", month)
+    ("month", month),
+    ("select", select),
+    ("num", num),
+    ("upper", upper),
+    ("lower", lower),
+    ("capitalize", capitalize),
+    ("title", title),
+    ("concat", concat),
+    ("pad", pad),
+    ("duration", duration),
+    ("date", date)
 ];
 
 // $(plural n 'plural_type_0' 'plural_type_1' ...)
 fn plural(args: &[template::Token]) -> Option<String> {
     if args.len() < 2 { return None; }
 
-    if let template::Token::NumberLit(n) = args[0] {
+    if let Some(n) = args[0].as_number() {
         let hachimi = Hachimi::instance();
-        let plural_type = 1 + hachimi.localized_data.load().plural_form.resolve(n as u64);
+        let plural_type = 1 + hachimi.localized_data.load().plural_form.resolve_number(n, args[0].fraction_digits())
+            .inspect_err(|e| warn!("{}", e))
+            .ok()?;
         let res = args.get(plural_type)?;
         if let template::Token::StringLit(str) = res {
-            return Some(str.replace("$", &n.to_string()));
+            return Some(str.replace("$", &args[0].to_string()));
         }
     }
 
@@ -24,11 +39,13 @@ fn plural(args: &[template::Token]) -> Option<String> {
 
 // $(ordinal n)
 fn ordinal(args: &[template::Token]) -> Option<String> {
-    if let template::Token::NumberLit(n) = args[0] {
+    if let Some(n) = args[0].as_number() {
         let localized_data = Hachimi::instance().localized_data.load();
-        let i = localized_data.ordinal_form.resolve(n as u64);
+        let i = localized_data.ordinal_form.resolve_number(n, args[0].fraction_digits())
+            .inspect_err(|e| warn!("{}", e))
+            .ok()?;
         let ordinal_type = localized_data.config.ordinal_types.get(i)?;
-        return Some(ordinal_type.replace("$", &n.to_string()));
+        return Some(ordinal_type.replace("$", &args[0].to_string()));
     }
 
     None
@@ -36,10 +53,316 @@ fn ordinal(args: &[template::Token]) -> Option<String> {
 
 // $(month n)
 fn month(args: &[template::Token]) -> Option<String> {
-    if let template::Token::NumberLit(i) = args[0] {
+    if let Some(i) = args[0].as_number() {
         let localized_data = Hachimi::instance().localized_data.load();
         return localized_data.config.months.get((i as usize).saturating_sub(1)).cloned();
     }
 
     None
+}
+
+// $(select key 'a:text_a' 'b:text_b' 'other:text_other')
+// An identifier key is resolved through the select_defaults config.
+fn select(args: &[template::Token]) -> Option<String> {
+    if args.len() < 2 { return None; }
+
+    let key = match &args[0] {
+        template::Token::Identifier(name) => {
+            let localized_data = Hachimi::instance().localized_data.load();
+            localized_data.config.select_defaults.get(name).cloned().unwrap_or_default()
+        }
+        template::Token::StringLit(str) => str.clone(),
+        template::Token::NumberLit { text, .. } => text.clone()
+    };
+
+    let mut other = None;
+    for arg in &args[1..] {
+        let template::Token::StringLit(option) = arg else {
+            return None;
+        };
+        let (option_key, text) = option.split_once(':')?;
+        if option_key == key {
+            return Some(text.to_owned());
+        }
+        else if option_key == "other" {
+            other = Some(text);
+        }
+    }
+
+    other.map(|text| text.to_owned())
+}
+
+// f64 has no more than 17 significant digits anyways
+const MAX_DECIMALS: usize = 17;
+
+// $(num n), $(num n decimals), $(num n 'compact') or $(num n 'compact' decimals)
+fn num(args: &[template::Token]) -> Option<String> {
+    let n = args.get(0)?.as_number()?;
+
+    let mut decimals = None;
+    let mut compact = false;
+    for arg in &args[1..] {
+        match arg {
+            template::Token::NumberLit { value, .. } => decimals = Some((*value as usize).min(MAX_DECIMALS)),
+            template::Token::StringLit(s) if s == "compact" => compact = true,
+            _ => return None
+        }
+    }
+
+    let localized_data = Hachimi::instance().localized_data.load();
+    let format = &localized_data.config.number_format;
+    if compact {
+        Some(format_compact_number(n, decimals.unwrap_or(format.compact_decimals).min(MAX_DECIMALS), format))
+    }
+    else {
+        Some(format_number(n, decimals, format))
+    }
+}
+
+fn format_number(n: f64, decimals: Option<usize>, format: &NumberFormat) -> String {
+    let digits = if let Some(decimals) = decimals {
+        format!("{:.*}", decimals, n.abs())
+    }
+    else {
+        n.abs().to_string()
+    };
+    let (int_part, frac_part) = match digits.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+        None => (digits.as_str(), None)
+    };
+
+    let mut output = String::with_capacity(digits.len() * 2);
+    if n < 0.0 {
+        output.push('-');
+    }
+
+    for (i, c) in int_part.chars().enumerate() {
+        let remaining = int_part.len() - i;
+        if i != 0 && format.grouping_size != 0 && remaining % format.grouping_size == 0 {
+            output.push_str(&format.grouping_separator);
+        }
+        output.push(c);
+    }
+
+    if let Some(frac_part) = frac_part {
+        output.push_str(&format.decimal_separator);
+        output.push_str(frac_part);
+    }
+
+    output
+}
+
+fn format_compact_number(n: f64, decimals: usize, format: &NumberFormat) -> String {
+    // Numbers below the smallest threshold are not compacted
+    let min_threshold = format.compact_forms.iter()
+        .map(|form| form.threshold)
+        .filter(|threshold| *threshold > 0.0)
+        .fold(f64::INFINITY, f64::min);
+    if n.abs() < min_threshold {
+        return format_number(n, None, format);
+    }
+
+    let mult = 10f64.powi(decimals as i32);
+    let mut res = None;
+    for form in format.compact_forms.iter() {
+        if form.threshold <= 0.0 { continue; }
+
+        // Check the rounded value so that 999,950 becomes 1M instead of 1000K
+        let value = (n / form.threshold * mult).round() / mult;
+        if value.abs() < 1.0 { continue; }
+
+        if res.map(|(prev, _): (f64, &str)| value.abs() < prev.abs()).unwrap_or(true) {
+            res = Some((value, form.suffix.as_str()));
+        }
+    }
+
+    if let Some((value, suffix)) = res {
+        let mut output = format_number(value, None, format);
+        output.push_str(suffix);
+        output
+    }
+    else {
+        format_number(n, None, format)
+    }
+}
+
+// $(upper 'text')
+fn upper(args: &[template::Token]) -> Option<String> {
+    let [text] = args else { return None; };
+    Some(text.to_string().to_uppercase())
+}
+
+// $(lower 'text')
+fn lower(args: &[template::Token]) -> Option<String> {
+    let [text] = args else { return None; };
+    Some(text.to_string().to_lowercase())
+}
+
+fn capitalize_str(str: &str) -> String {
+    let mut chars = str.chars();
+    let Some(first) = chars.next() else {
+        return String::new();
+    };
+    // Some chars have multi-char uppercase forms (e.g. ß -> SS)
+    first.to_uppercase().chain(chars).collect()
+}
+
+// $(capitalize 'text')
+fn capitalize(args: &[template::Token]) -> Option<String> {
+    let [text] = args else { return None; };
+    Some(capitalize_str(&text.to_string()))
+}
+
+// $(title 'text')
+fn title(args: &[template::Token]) -> Option<String> {
+    let [text] = args else { return None; };
+    let text = text.to_string();
+
+    let mut res = String::with_capacity(text.len());
+    let mut word_start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = word_start.take() {
+                res.push_str(&capitalize_str(&text[start..i]));
+            }
+            res.push(c);
+        }
+        else if word_start.is_none() {
+            word_start = Some(i);
+        }
+    }
+    if let Some(start) = word_start {
+        res.push_str(&capitalize_str(&text[start..]));
+    }
+
+    Some(res)
+}
+
+// $(concat 'a' 'b' ...)
+fn concat(args: &[template::Token]) -> Option<String> {
+    Some(args.iter().map(|arg| arg.to_string()).collect())
+}
+
+// Upper bound for the width, so that a typo in a dict can't allocate huge strings
+const MAX_PAD_WIDTH: usize = 1024;
+
+// $(pad 'text' width), $(pad 'text' width 'fill') or $(pad 'text' width 'fill' 'start')
+// Width is measured in display columns (CJK chars are 2 columns wide).
+fn pad(args: &[template::Token]) -> Option<String> {
+    let text = args.get(0)?.to_string();
+    let width = (args.get(1)?.as_number()? as usize).min(MAX_PAD_WIDTH);
+
+    let fill = match args.get(2) {
+        Some(template::Token::StringLit(fill)) => fill.as_str(),
+        None => " ",
+        _ => return None
+    };
+    let pad_start = match args.get(3) {
+        Some(template::Token::StringLit(side)) if side == "start" => true,
+        Some(template::Token::StringLit(side)) if side == "end" => false,
+        None => false,
+        _ => return None
+    };
+
+    let fill_width = fill.width();
+    let text_width = text.width();
+    if fill_width == 0 || text_width >= width {
+        return Some(text);
+    }
+
+    let padding = fill.repeat((width - text_width) / fill_width);
+    Some(if pad_start { padding + &text } else { text + &padding })
+}
+
+fn format_duration_unit(localized_data: &LocalizedData, forms: &[String], value: i64) -> Option<String> {
+    let i = localized_data.plural_form.resolve(value as u64)
+        .inspect_err(|e| warn!("{}", e))
+        .ok()?;
+    let form = forms.get(i).or_else(|| forms.last())?;
+    Some(form.replace("$", &value.to_string()))
+}
+
+// $(duration seconds), $(duration seconds 'long') or $(duration seconds 'short' max_units)
+// Shows up to 2 units by default, e.g. $(duration 3725 'short') = "1h 2m"
+fn duration(args: &[template::Token]) -> Option<String> {
+    let secs = args.get(0)?.as_number()?;
+    let long = match args.get(1) {
+        Some(template::Token::StringLit(style)) if style == "long" => true,
+        Some(template::Token::StringLit(style)) if style == "short" => false,
+        None => false,
+        _ => return None
+    };
+    let max_units = match args.get(2) {
+        Some(template::Token::NumberLit { value, .. }) => (*value as usize).max(1),
+        None => 2,
+        _ => return None
+    };
+
+    let delta = TimeDelta::try_seconds(secs.abs() as i64)?;
+    let localized_data = Hachimi::instance().localized_data.load();
+    let format = &localized_data.config.duration_format;
+    let units = if long { format.long.as_ref().unwrap_or(&format.short) } else { &format.short };
+
+    let values = [
+        (&units.days, delta.num_days()),
+        (&units.hours, delta.num_hours() % 24),
+        (&units.minutes, delta.num_minutes() % 60),
+        (&units.seconds, delta.num_seconds() % 60)
+    ];
+
+    // Only the max_units largest units starting from the first non-zero one are shown
+    // (e.g. 3605 secs = "1h", not "1h 5s")
+    let first = values.iter().position(|(_, value)| *value != 0).unwrap_or(values.len());
+    let mut parts = Vec::with_capacity(values.len());
+    for (forms, value) in values.into_iter().skip(first).take(max_units) {
+        if value == 0 { continue; }
+        parts.push(format_duration_unit(&localized_data, forms, value)?);
+    }
+    if parts.is_empty() {
+        parts.push(format_duration_unit(&localized_data, &units.seconds, 0)?);
+    }
+
+    Some(parts.join(&format.separator))
+}
+
+struct DateContext {
+    date: NaiveDate
+}
+
+impl template::Context for DateContext {
+    fn on_filter_eval(&mut self, name: &str, _args: &[template::Token]) -> Option<String> {
+        // A date inside of date_format would recurse forever
+        if name == "date" {
+            warn!("date filter used inside of date_format");
+            return Some(String::new());
+        }
+        None
+    }
+
+    fn get_variable(&mut self, name: &str) -> Option<template::Token> {
+        let value = match name {
+            "year" => self.date.year(),
+            "month" => self.date.month() as i32,
+            "day" => self.date.day() as i32,
+            "weekday" => self.date.weekday().number_from_monday() as i32,
+            _ => return None
+        };
+        Some(template::Token::NumberLit { value: value as f64, text: value.to_string() })
+    }
+}
+
+// $(date year month day)
+fn date(args: &[template::Token]) -> Option<String> {
+    let [year, month, day] = args else {
+        return None;
+    };
+    let date = NaiveDate::from_ymd_opt(year.as_number()? as i32, month.as_number()? as u32, day.as_number()? as u32)?;
+
+    let hachimi = Hachimi::instance();
+    let localized_data = hachimi.localized_data.load();
+    let Some(format) = &localized_data.config.date_format else {
+        return Some(date.format("%Y-%m-%d").to_string());
+    };
+
+    Some(hachimi.template_parser.eval_with_context(format, &mut DateContext { date }))
 }
\ No newline at end of file
@@ -8,7 +8,7 @@ use size::Size;
 use thread_priority::{ThreadBuilderExt, ThreadPriority};
 
 use crate::core::game::Region;
-use super::{gui::SimpleYesNoDialog, hachimi::LocalizedData, http::{self, AsyncRequest}, utils, Error, Gui, Hachimi};
+use super::{gui::SimpleYesNoDialog, http::{self, AsyncRequest}, utils, Error, Gui, Hachimi};
 use once_cell::sync::Lazy;
 
 #[derive(Deserialize)]
@@ -42,15 +42,19 @@ struct RepoFile {
 
 impl RepoFile {
     fn get_fs_path(&self, root_dir: &Path) -> PathBuf {
-        // Modern Windows versions support forward slashes anyways but it doesn't hurt to do something so trivial
-        #[cfg(target_os = "windows")]
-        return root_dir.join(&self.path.replace("/", "\\"));
-
-        #[cfg(not(target_os = "windows"))]
-        return root_dir.join(&self.path);
+        repo_fs_path(root_dir, &self.path)
     }
 }
 
+fn repo_fs_path(root_dir: &Path, path: &str) -> PathBuf {
+    // Modern Windows versions support forward slashes anyways but it doesn't hurt to do something so trivial
+    #[cfg(target_os = "windows")]
+    return root_dir.join(path.replace("/", "\\"));
+
+    #[cfg(not(target_os = "windows"))]
+    return root_dir.join(path);
+}
+
 #[derive(Clone)]
 struct UpdateInfo {
     base_url: String,
@@ -88,6 +92,27 @@ struct RepoCache {
     files: FnvHashMap<String, String> // path: hash
 }
 
+/// Hashes of the downloaded files, keyed by their path on disk.
+pub fn load_repo_hashes(data_dir: &Path) -> FnvHashMap<PathBuf, String> {
+    let cache_path = data_dir.join(REPO_CACHE_FILENAME);
+    let Ok(json) = fs::read_to_string(&cache_path) else {
+        return Default::default();
+    };
+    let repo_cache: RepoCache = match serde_json::from_str(&json) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Failed to parse repo cache: {}", e);
+            return Default::default();
+        }
+    };
+
+    let localized_data_dir = data_dir.join(LOCALIZED_DATA_DIR);
+    repo_cache.files
+        .into_iter()
+        .map(|(path, hash)| (repo_fs_path(&localized_data_dir, &path), hash))
+        .collect()
+}
+
 #[derive(Default)]
 pub struct Updater {
     update_check_mutex: Mutex<()>,
@@ -339,7 +364,7 @@ impl Updater {
 
         // Empty the localized data so files couldnt be accessed while update is in progress
         let hachimi = Hachimi::instance();
-        hachimi.localized_data.store(Arc::new(LocalizedData::default()));
+        hachimi.unload_localized_data();
 
         // Clear the localized data if downloading from a new repo
         let localized_data_dir = hachimi.get_data_path(LOCALIZED_DATA_DIR);
@@ -373,9 +398,6 @@ impl Updater {
         // Drop the download state
         self.progress.store(Arc::new(None));
 
-        // Reload the localized data
-        hachimi.load_localized_data();
-
         // Save the repo cache (done last so if any of the previous fails, the entire update would be voided)
         let repo_cache = RepoCache {
             base_url: update_info.base_url.clone(),
@@ -384,6 +406,9 @@ impl Updater {
         let cache_path = hachimi.get_data_path(REPO_CACHE_FILENAME);
         utils::write_json_file(&repo_cache, &cache_path)?;
 
+        // Reload the localized data, after saving the repo cache since the dict cache uses its hashes
+        _ = hachimi.load_localized_data();
+
         if let Some(mutex) = Gui::instance() {
             let mut gui = mutex.lock().unwrap();
             gui.show_notification(&t!("notification.update_completed"));
@@ -6,7 +6,7 @@ use unicode_width::UnicodeWidthChar;
 
 use crate::{core::Gui, il2cpp::{ext::{Il2CppStringExt, StringExt}, types::Il2CppString}};
 
-use super::{Error, Hachimi};
+use super::{template, Error, Hachimi};
 
 pub fn concat_unix_path(left: &str, right: &str) -> String {
     let mut str = String::with_capacity(left.len() + 1 + right.len());
@@ -56,6 +56,9 @@ impl<'a> Iterator for IsolateTags<'a> {
         // Template expressions
         let mut expecting_expr_open = false;
         let mut in_expression = false;
+        let mut expr_depth = 0;
+        let mut in_string = false;
+        let mut string_escape = false;
 
         while let Some(c) = self.current_byte {
             if in_tag {
@@ -113,8 +116,32 @@ impl<'a> Iterator for IsolateTags<'a> {
                 }
             }
             else if in_expression {
-                if c == b')'  {
-                    if !self.s[self.i..].contains(")") {
+                // String literals ('...' with \' escapes) can contain parentheses,
+                // they only start at the beginning of a token like in the template parser
+                if in_string {
+                    if string_escape {
+                        string_escape = false;
+                    }
+                    else if c == b'\\' {
+                        string_escape = true;
+                    }
+                    else if c == b'\'' {
+                        in_string = false;
+                    }
+                }
+                else if c == b'\'' && matches!(self.s.as_bytes()[self.i - 1], b'(' | b' ' | b'\n' | b'\r' | b'\t') {
+                    in_string = true;
+                }
+                else if c == b'(' {
+                    // Nested expression
+                    expr_depth += 1;
+                }
+                else if c == b')' {
+                    expr_depth -= 1;
+                    if expr_depth != 0 {
+                        // Still inside of the outer expression
+                    }
+                    else if !self.s[self.i..].contains(")") {
                         in_expression = false;
                     }
                     else {
@@ -143,7 +170,8 @@ impl<'a> Iterator for IsolateTags<'a> {
                 }
             }
             else if c == b'$' {
-                expecting_expr_open = true;
+                // Same rules as the template parser, $$( is an escaped expression
+                expecting_expr_open = template::is_expr_start(self.s.as_bytes(), self.i);
             }
             else if c == b'(' {
                 if expecting_expr_open {
@@ -154,6 +182,7 @@ impl<'a> Iterator for IsolateTags<'a> {
                         break;
                     }
                     in_expression = true;
+                    expr_depth = 1;
                     expecting_expr_open = false;
                 }
             }
@@ -214,7 +243,10 @@ fn custom_wrap_algorithm<'a, 'b>(words: &'b [Word<'a>], line_widths: &'b [usize]
     let mut remove_offset = 0;
     for (i, word) in words.iter().enumerate() {
         let is_tag = word.starts_with("<") && word.ends_with(">");
-        let is_expr = word.starts_with("$(") && word.ends_with(")");
+        // Escaped expressions ($$() might get split into "$" and "$(...)" by the line breaker,
+        // in which case the previous word ends with the escaping dollar sign
+        let is_escaped = i > 0 && words[i - 1].whitespace.is_empty() && words[i - 1].word.ends_with('$');
+        let is_expr = word.starts_with("$(") && word.ends_with(")") && !is_escaped;
         if is_tag || is_expr {
             removed_indices.push(i - remove_offset);
             remove_offset += 1;
@@ -471,6 +503,23 @@ pub fn write_json_file<T: Serialize, P: AsRef<Path>>(data: &T, path: P) -> Resul
     Ok(())
 }
 
+/// Recursively merges `value` into `base`. Objects are merged per key, anything else is replaced.
+pub fn merge_json_value(base: &mut serde_json::Value, value: serde_json::Value) {
+    match (base, value) {
+        (serde_json::Value::Object(base_map), serde_json::Value::Object(map)) => {
+            for (k, v) in map {
+                match base_map.get_mut(&k) {
+                    Some(base_v) => merge_json_value(base_v, v),
+                    None => {
+                        base_map.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
 // Checks for both \n and \\n
 pub fn game_str_has_newline(string: *mut Il2CppString) -> bool {
     let mut got_backslash = false;
@@ -0,0 +1,679 @@
+//! XLIFF 2.0 export/import for story timeline dicts and race story text lists.
+//!
+//! Each dict file becomes one XLIFF document, mirroring its path in the assets dir.
+//! - Story timelines: a `title` unit, then one group per text block (`b<index>`) containing the
+//!   `-name`, `-text`, `-choice-<n>` and `-color-<n>` units of that block.
+//! - Race story texts: one `t<index>` unit per line.
+//!
+//! Block indices, speaker names, `new_clip_length` and `no_wrap` are kept in `mda:metadata`
+//! so that importing an exported file gives back the exact same dict.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::il2cpp::hook::umamusume::StoryTimelineData::{StoryTimelineDataDict, TextBlockDict};
+
+use super::{hachimi::LocalizedData, utils, Error};
+
+const META_CATEGORY: &str = "hachimi";
+const FORMAT_STORY_TIMELINE: &str = "story_timeline";
+const FORMAT_RACE_TEXT: &str = "race_text";
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum StoryDict {
+    Timeline(StoryTimelineDataDict),
+    RaceText(Vec<String>),
+}
+
+impl StoryDict {
+    /// Only JSON files that look like story dicts are accepted, the assets dir also contains asset metadata.
+    fn from_json(json: &str) -> Option<StoryDict> {
+        let value: serde_json::Value = serde_json::from_str(json).ok()?;
+        match &value {
+            serde_json::Value::Array(items) if items.iter().all(|v| v.is_string()) => {
+                serde_json::from_value(value).ok().map(StoryDict::RaceText)
+            }
+            serde_json::Value::Object(map)
+                if ["text_block_list", "TextBlockList", "title", "Title"]
+                    .iter()
+                    .any(|k| map.contains_key(*k)) =>
+            {
+                serde_json::from_value(value).ok().map(StoryDict::Timeline)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+struct Writer {
+    out: String,
+    depth: usize,
+}
+
+impl Writer {
+    fn line(&mut self, s: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(s);
+        self.out.push('\n');
+    }
+
+    fn metadata(&mut self, metas: &[(&str, String)]) {
+        if metas.is_empty() {
+            return;
+        }
+        self.line("<mda:metadata>");
+        self.depth += 1;
+        self.line(&format!("<mda:metaGroup category=\"{}\">", META_CATEGORY));
+        self.depth += 1;
+        for (ty, value) in metas {
+            self.line(&format!("<mda:meta type=\"{}\">{}</mda:meta>", ty, escape(value)));
+        }
+        self.depth -= 1;
+        self.line("</mda:metaGroup>");
+        self.depth -= 1;
+        self.line("</mda:metadata>");
+    }
+
+    /// Source falls back to the translation when the original text is not available.
+    fn unit(&mut self, id: &str, metas: &[(&str, String)], original: Option<&String>, target: Option<&String>) {
+        let source = match (original, target) {
+            (Some(original), _) => original,
+            (None, Some(target)) => target,
+            (None, None) => return,
+        };
+
+        self.line(&format!("<unit id=\"{}\">", escape(id)));
+        self.depth += 1;
+        self.metadata(metas);
+        if original.is_none() {
+            self.line("<notes>");
+            self.depth += 1;
+            self.line("<note category=\"hachimi\">Original text not available</note>");
+            self.depth -= 1;
+            self.line("</notes>");
+        }
+        let state = if target.is_some() { " state=\"translated\"" } else { "" };
+        self.line(&format!("<segment{}>", state));
+        self.depth += 1;
+        // Text is written inline to keep whitespace intact
+        self.line(&format!("<source xml:space=\"preserve\">{}</source>", escape(source)));
+        if let Some(target) = target {
+            self.line(&format!("<target xml:space=\"preserve\">{}</target>", escape(target)));
+        }
+        self.depth -= 1;
+        self.line("</segment>");
+        self.depth -= 1;
+        self.line("</unit>");
+    }
+}
+
+pub fn write_xliff(rel_path: &str, dict: &StoryDict, original: Option<&StoryDict>, trg_lang: Option<&str>) -> String {
+    let mut w = Writer { out: String::new(), depth: 0 };
+    w.line("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    let trg_lang_attr = trg_lang.map(|l| format!(" trgLang=\"{}\"", escape(l))).unwrap_or_default();
+    w.line(&format!(
+        "<xliff xmlns=\"urn:oasis:names:tc:xliff:document:2.0\" \
+        xmlns:mda=\"urn:oasis:names:tc:xliff:metadata:2.0\" version=\"2.0\" srcLang=\"ja\"{}>",
+        trg_lang_attr
+    ));
+    w.depth += 1;
+    w.line(&format!("<file id=\"f1\" original=\"{}\">", escape(rel_path)));
+    w.depth += 1;
+
+    match dict {
+        StoryDict::Timeline(dict) => {
+            let original = match original {
+                Some(StoryDict::Timeline(o)) => Some(o),
+                _ => None,
+            };
+
+            let mut file_metas = vec![("format", FORMAT_STORY_TIMELINE.to_owned())];
+            if dict.no_wrap {
+                file_metas.push(("no_wrap", "true".to_owned()));
+            }
+            w.metadata(&file_metas);
+
+            w.unit("title", &[], original.and_then(|o| o.title.as_ref()), dict.title.as_ref());
+
+            for (i, block) in dict.text_block_list.iter().enumerate() {
+                let orig_block = original.and_then(|o| o.text_block_list.get(i));
+                let index_meta = ("block_index", i.to_string());
+
+                w.line(&format!("<group id=\"b{}\">", i));
+                w.depth += 1;
+                let mut group_metas = vec![index_meta.clone()];
+                if let Some(len) = block.new_clip_length {
+                    group_metas.push(("new_clip_length", len.to_string()));
+                }
+                w.metadata(&group_metas);
+
+                let mut text_metas = vec![index_meta.clone()];
+                if let Some(name) = &block.name {
+                    text_metas.push(("speaker", name.clone()));
+                }
+
+                w.unit(
+                    &format!("b{}-name", i),
+                    &[index_meta.clone()],
+                    orig_block.and_then(|o| o.name.as_ref()),
+                    block.name.as_ref(),
+                );
+                w.unit(
+                    &format!("b{}-text", i),
+                    &text_metas,
+                    orig_block.and_then(|o| o.text.as_ref()),
+                    block.text.as_ref(),
+                );
+                for (j, choice) in block.choice_data_list.iter().enumerate() {
+                    w.unit(
+                        &format!("b{}-choice-{}", i, j),
+                        &[index_meta.clone()],
+                        orig_block.and_then(|o| o.choice_data_list.get(j)),
+                        Some(choice),
+                    );
+                }
+                for (j, color_text) in block.color_text_info_list.iter().enumerate() {
+                    w.unit(
+                        &format!("b{}-color-{}", i, j),
+                        &[index_meta.clone()],
+                        orig_block.and_then(|o| o.color_text_info_list.get(j)),
+                        Some(color_text),
+                    );
+                }
+
+                w.depth -= 1;
+                w.line("</group>");
+            }
+        }
+
+        StoryDict::RaceText(list) => {
+            let original = match original {
+                Some(StoryDict::RaceText(o)) => Some(o),
+                _ => None,
+            };
+
+            w.metadata(&[("format", FORMAT_RACE_TEXT.to_owned()), ("length", list.len().to_string())]);
+            for (i, text) in list.iter().enumerate() {
+                w.unit(
+                    &format!("t{}", i),
+                    &[("index", i.to_string())],
+                    original.and_then(|o| o.get(i)),
+                    Some(text),
+                );
+            }
+        }
+    }
+
+    w.depth -= 1;
+    w.line("</file>");
+    w.depth -= 1;
+    w.line("</xliff>");
+    w.out
+}
+
+/// Parses an XLIFF document back into the dict file path and the dict.
+pub fn parse_xliff(input: &str) -> Result<(String, StoryDict), Error> {
+    let root = XmlParser::new(input).parse_document()?;
+    if root.local_name() != "xliff" {
+        return Err(Error::RuntimeError("Not an XLIFF document".to_owned()));
+    }
+    let file = root
+        .child("file")
+        .ok_or_else(|| Error::RuntimeError("XLIFF document has no file element".to_owned()))?;
+    let rel_path = file
+        .attr("original")
+        .ok_or_else(|| Error::RuntimeError("XLIFF file element has no original attribute".to_owned()))?
+        .to_owned();
+
+    let file_metas = file.metas();
+    let format = meta_value(&file_metas, "format").unwrap_or(FORMAT_STORY_TIMELINE);
+
+    let dict = if format == FORMAT_RACE_TEXT {
+        let units: Vec<_> = file.elements().filter(|e| e.local_name() == "unit").collect();
+        let len = meta_value(&file_metas, "length").and_then(|s| s.parse().ok()).unwrap_or(0);
+        check_index(len, units.len(), "Length")?;
+        let mut list = vec![String::new(); len];
+        for unit in units {
+            let Some(index) = meta_value(&unit.metas(), "index")
+                .or_else(|| unit.attr("id").and_then(|id| id.strip_prefix('t')))
+                .and_then(|s| s.parse::<usize>().ok())
+            else {
+                continue;
+            };
+            if index >= list.len() {
+                check_index(index, list.len(), "Text index")?;
+                list.resize(index + 1, String::new());
+            }
+            list[index] = unit.target().unwrap_or_default();
+        }
+        StoryDict::RaceText(list)
+    } else {
+        let mut dict = StoryTimelineDataDict {
+            no_wrap: meta_value(&file_metas, "no_wrap") == Some("true"),
+            ..Default::default()
+        };
+
+        for element in file.elements() {
+            match element.local_name() {
+                "unit" if element.attr("id") == Some("title") => dict.title = element.target(),
+                "group" => {
+                    let group_metas = element.metas();
+                    let Some(index) = meta_value(&group_metas, "block_index")
+                        .or_else(|| element.attr("id").and_then(|id| id.strip_prefix('b')))
+                        .and_then(|s| s.parse::<usize>().ok())
+                    else {
+                        continue;
+                    };
+                    if index >= dict.text_block_list.len() {
+                        check_index(index, dict.text_block_list.len(), "Block index")?;
+                        dict.text_block_list.resize_with(index + 1, TextBlockDict::default);
+                    }
+                    let block = &mut dict.text_block_list[index];
+                    block.new_clip_length = meta_value(&group_metas, "new_clip_length").and_then(|s| s.parse().ok());
+
+                    let prefix = format!("b{}-", index);
+                    for unit in element.elements().filter(|e| e.local_name() == "unit") {
+                        let Some(field) = unit.attr("id").and_then(|id| id.strip_prefix(prefix.as_str())) else {
+                            continue;
+                        };
+                        let target = unit.target();
+                        match field {
+                            "name" => block.name = target,
+                            "text" => block.text = target,
+                            _ => {
+                                let (list, j) = if let Some(j) = field.strip_prefix("choice-") {
+                                    (&mut block.choice_data_list, j)
+                                } else if let Some(j) = field.strip_prefix("color-") {
+                                    (&mut block.color_text_info_list, j)
+                                } else {
+                                    continue;
+                                };
+                                let Ok(j) = j.parse::<usize>() else { continue };
+                                if j >= list.len() {
+                                    check_index(j, list.len(), "Choice/color index")?;
+                                    list.resize(j + 1, String::new());
+                                }
+                                list[j] = target.unwrap_or_default();
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        StoryDict::Timeline(dict)
+    };
+
+    Ok((rel_path, dict))
+}
+
+// Indices come from the imported file, a typo shouldn't be able to allocate a huge list
+const MAX_INDEX_GAP: usize = 1024;
+
+fn check_index(index: usize, len: usize, what: &str) -> Result<(), Error> {
+    if index > len.saturating_add(MAX_INDEX_GAP) {
+        return Err(Error::RuntimeError(format!("{} {} is too large for a list of {} entries", what, index, len)));
+    }
+    Ok(())
+}
+
+fn meta_value<'a>(metas: &'a [(String, String)], ty: &str) -> Option<&'a str> {
+    metas.iter().find(|(t, _)| t == ty).map(|(_, v)| v.as_str())
+}
+
+fn collect_json_files(dir: &Path, rel_dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let rel_path = rel_dir.join(entry.file_name());
+        if path.is_dir() {
+            collect_json_files(&path, &rel_path, out);
+        } else if path.extension().is_some_and(|e| e == "json") {
+            out.push(rel_path);
+        }
+    }
+}
+
+/// Exports every story dict in the assets dirs to `out_dir`, one XLIFF file per dict.
+/// Original texts are read from dicts with the same paths in `originals_dir`, if provided.
+pub fn export_dir(
+    ld: &LocalizedData,
+    originals_dir: Option<&Path>,
+    out_dir: &Path,
+    trg_lang: Option<&str>,
+) -> Result<usize, Error> {
+    let mut rel_paths = Vec::new();
+    for assets_dir in ld.assets_dirs() {
+        collect_json_files(assets_dir, Path::new(""), &mut rel_paths);
+    }
+    rel_paths.sort();
+    rel_paths.dedup();
+
+    let mut count = 0;
+    for rel_path in rel_paths {
+        // Goes through the layers so that overrides are exported
+        let Some(path) = ld.get_assets_path(&rel_path) else {
+            continue;
+        };
+        let Some(dict) = fs::read_to_string(&path).ok().and_then(|json| StoryDict::from_json(&json)) else {
+            continue;
+        };
+        let original = originals_dir
+            .and_then(|dir| fs::read_to_string(dir.join(&rel_path)).ok())
+            .and_then(|json| StoryDict::from_json(&json));
+
+        let rel_path_str = rel_path.to_string_lossy().replace('\\', "/");
+        let xliff = write_xliff(&rel_path_str, &dict, original.as_ref(), trg_lang);
+
+        let out_path = out_dir.join(&rel_path).with_extension("xlf");
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&out_path, xliff)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Imports every XLIFF file in `in_dir`, writing the dicts to the highest priority assets dir.
+pub fn import_dir(ld: &LocalizedData, in_dir: &Path) -> Result<usize, Error> {
+    let Some(assets_dir) = ld.assets_dirs().first() else {
+        return Err(Error::RuntimeError("No assets dir configured".to_owned()));
+    };
+
+    let mut files = Vec::new();
+    collect_xliff_files(in_dir, &mut files);
+
+    let mut count = 0;
+    for path in files {
+        let (rel_path, dict) = parse_xliff(&fs::read_to_string(&path)?)
+            .map_err(|e| Error::RuntimeError(format!("{}: {}", path.display(), e)))?;
+        // Only plain relative paths, no "..", roots or drive prefixes (e.g. "C:foo" on Windows)
+        if rel_path.is_empty() || !Path::new(&rel_path).components().all(|c| matches!(c, Component::Normal(_))) {
+            warn!("Dict path '{}' sanitized", rel_path);
+            continue;
+        }
+
+        let out_path = assets_dir.join(&rel_path);
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        utils::write_json_file(&dict, &out_path)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn collect_xliff_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_xliff_files(&path, out);
+        } else if path.extension().is_some_and(|e| e == "xlf" || e == "xliff") {
+            out.push(path);
+        }
+    }
+}
+
+// Minimal XML DOM, only what's needed to read back XLIFF documents.
+// Inline markup added by CAT tools (e.g. <ph>, <mrk>) is flattened to its text content.
+
+enum XmlNode {
+    Element(Element),
+    Text(String),
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<XmlNode>,
+}
+
+impl Element {
+    fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or(&self.name)
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c {
+            XmlNode::Element(e) => Some(e),
+            XmlNode::Text(_) => None,
+        })
+    }
+
+    fn child(&self, local_name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.local_name() == local_name)
+    }
+
+    fn text(&self) -> String {
+        let mut s = String::new();
+        for child in self.children.iter() {
+            match child {
+                XmlNode::Element(e) => s.push_str(&e.text()),
+                XmlNode::Text(t) => s.push_str(t),
+            }
+        }
+        s
+    }
+
+    /// Hachimi metadata of this element as (type, value) pairs.
+    fn metas(&self) -> Vec<(String, String)> {
+        let Some(metadata) = self.child("metadata") else {
+            return Vec::new();
+        };
+        metadata
+            .elements()
+            .filter(|g| g.local_name() == "metaGroup" && g.attr("category") == Some(META_CATEGORY))
+            .flat_map(|g| g.elements())
+            .filter(|m| m.local_name() == "meta")
+            .filter_map(|m| Some((m.attr("type")?.to_owned(), m.text())))
+            .collect()
+    }
+
+    /// Target text of the unit, joining all of its segments.
+    fn target(&self) -> Option<String> {
+        let mut target: Option<String> = None;
+        for segment in self.elements().filter(|e| matches!(e.local_name(), "segment" | "ignorable")) {
+            if let Some(t) = segment.child("target") {
+                target.get_or_insert_with(String::new).push_str(&t.text());
+            }
+        }
+        target
+    }
+}
+
+struct XmlParser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    fn new(s: &'a str) -> Self {
+        XmlParser { s, pos: 0 }
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let line = self.s[..self.pos.min(self.s.len())].matches('\n').count() + 1;
+        Error::RuntimeError(format!("XML parse error at line {}: {}", line, msg))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), Error> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("expected '{}'", end))),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Element, Error> {
+        loop {
+            self.skip_ws();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                break;
+            }
+        }
+        self.parse_element()
+    }
+
+    fn parse_name(&mut self) -> Result<String, Error> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_owned())
+    }
+
+    fn parse_element(&mut self) -> Result<Element, Error> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected '<'"));
+        }
+        self.pos += 1;
+        let name = self.parse_name()?;
+        let mut element = Element { name, attrs: Vec::new(), children: Vec::new() };
+
+        loop {
+            self.skip_ws();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let attr_name = self.parse_name()?;
+            self.skip_ws();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("expected '='"));
+            }
+            self.pos += 1;
+            self.skip_ws();
+            let quote = self.rest().chars().next().filter(|c| *c == '"' || *c == '\'');
+            let Some(quote) = quote else {
+                return Err(self.error("expected a quoted attribute value"));
+            };
+            self.pos += 1;
+            let Some(len) = self.rest().find(quote) else {
+                return Err(self.error("unterminated attribute value"));
+            };
+            let value = self.unescape(&self.rest()[..len])?;
+            self.pos += len + 1;
+            element.attrs.push((attr_name, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(&format!("unclosed element '{}'", element.name)));
+            }
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.parse_name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("expected '</{}>'", element.name)));
+                }
+                self.skip_past(">")?;
+                return Ok(element);
+            }
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let Some(len) = cdata.find("]]>") else {
+                    return Err(self.error("unterminated CDATA section"));
+                };
+                element.children.push(XmlNode::Text(cdata[..len].to_owned()));
+                self.pos += "<![CDATA[".len() + len + "]]>".len();
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                element.children.push(XmlNode::Element(self.parse_element()?));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                element.children.push(XmlNode::Text(self.unescape(&rest[..len])?));
+                self.pos += len;
+            }
+        }
+    }
+
+    fn unescape(&self, s: &str) -> Result<String, Error> {
+        let mut res = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(i) = rest.find('&') {
+            res.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            let Some(end) = rest.find(';') else {
+                return Err(self.error("unterminated entity"));
+            };
+            let entity = &rest[..end];
+            let c = match entity {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                    .and_then(|n| n.ok())
+                    .and_then(char::from_u32),
+            };
+            let Some(c) = c else {
+                return Err(self.error(&format!("unknown entity '&{};'", entity)));
+            };
+            res.push(c);
+            rest = &rest[end + 1..];
+        }
+        res.push_str(rest);
+        Ok(res)
+    }
+}
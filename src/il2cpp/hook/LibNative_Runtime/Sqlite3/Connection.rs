@@ -3,17 +3,89 @@ use std::sync::Mutex;
 use fnv::FnvHashMap;
 use once_cell::sync::Lazy;
 use sqlparser::{
-    ast::BinaryOperator,
     dialect::SQLiteDialect,
     keywords::Keyword,
     parser::Parser
 };
 
-use crate::il2cpp::{ext::Il2CppStringExt, sql::{self, ExprExt, SelectExt, SelectItemExt}, symbols::get_method_addr, types::*};
+use crate::{
+    core::Hachimi,
+    il2cpp::{ext::Il2CppStringExt, sql::{self, SelectExt, SelectItemExt}, symbols::get_method_addr, types::*}
+};
 
 pub static SELECT_QUERIES: Lazy<Mutex<FnvHashMap<usize, Box<dyn sql::SelectQueryState + Send + Sync>>>> =
     Lazy::new(|| Mutex::new(FnvHashMap::default()));
 
+type BoxedQueryState = Box<dyn sql::SelectQueryState + Send + Sync>;
+
+// The game issues the same few statements thousands of times, so the parsed state is cached per SQL string
+// and cloned for each new query. Statements that aren't translated are cached too since they're the majority.
+const QUERY_CACHE_CAPACITY: usize = 512;
+
+struct QueryCacheEntry {
+    template: Option<BoxedQueryState>,
+    last_used: u64
+}
+
+#[derive(Default)]
+struct QueryCache {
+    entries: FnvHashMap<String, QueryCacheEntry>,
+    use_counter: u64,
+    hits: usize,
+    misses: usize
+}
+
+static QUERY_CACHE: Lazy<Mutex<QueryCache>> = Lazy::new(|| Mutex::new(QueryCache::default()));
+
+#[derive(Clone, Copy)]
+pub struct QueryCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub len: usize
+}
+
+pub fn query_cache_stats() -> QueryCacheStats {
+    let cache = QUERY_CACHE.lock().unwrap();
+    QueryCacheStats {
+        hits: cache.hits,
+        misses: cache.misses,
+        len: cache.entries.len()
+    }
+}
+
+/// Must be called when the localized data config changes, since it decides which tables are handled.
+pub fn clear_query_cache() {
+    QUERY_CACHE.lock().unwrap().entries.clear();
+}
+
+impl QueryCache {
+    /// Returns None if the statement isn't cached, Some(None) if it's cached as not handled.
+    fn get(&mut self, sql: &str) -> Option<Option<BoxedQueryState>> {
+        self.use_counter += 1;
+        let last_used = self.use_counter;
+        let Some(entry) = self.entries.get_mut(sql) else {
+            self.misses += 1;
+            return None;
+        };
+        entry.last_used = last_used;
+        self.hits += 1;
+        Some(entry.template.as_ref().map(|t| t.clone_box()))
+    }
+
+    fn insert(&mut self, sql: String, template: Option<BoxedQueryState>) {
+        if self.entries.len() >= QUERY_CACHE_CAPACITY {
+            let lru_key = self.entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(lru_key) = lru_key {
+                self.entries.remove(&lru_key);
+            }
+        }
+        self.entries.insert(sql, QueryCacheEntry { template, last_used: self.use_counter });
+    }
+}
+
 #[inline(never)]
 fn parse_query(query: *mut Il2CppObject, sql: *const Il2CppString) {
     let sql_str = unsafe { (*sql).as_utf16str() }.to_string();
@@ -23,62 +95,69 @@ fn parse_query(query: *mut Il2CppObject, sql: *const Il2CppString) {
         return;
     }
 
-    // parse the sql string
-    let dialect = SQLiteDialect {};
-    let parser_res = Parser::new(&dialect).try_with_sql(&sql_str);
-
-    if let Ok(mut parser) = parser_res {
-        // only care about select statements
-        if !parser.parse_keyword(Keyword::SELECT) {
-            return;
+    let cached = QUERY_CACHE.lock().unwrap().get(&sql_str);
+    let query_state = match cached {
+        Some(query_state) => query_state,
+        None => {
+            let query_state = build_query_state(&sql_str);
+            let template = query_state.as_ref().map(|s| s.clone_box());
+            QUERY_CACHE.lock().unwrap().insert(sql_str, template);
+            query_state
         }
-        let Ok(select) = parser.parse_select() else {
-            return;
-        };
+    };
 
-        // and their first table name (SELECT FROM table_name)
-        let Some(table_name) = select.get_first_table_name() else {
-            debug!("no table name");
-            return;
-        };
+    // Add query state
+    if let Some(query_state) = query_state {
+        SELECT_QUERIES.lock().unwrap().insert(query as usize, query_state);
+    }
+}
 
-        // Create the query state
-        let mut query_state: Box<dyn sql::SelectQueryState + Send + Sync> = match table_name.as_ref() {
-            "text_data" => Box::new(sql::TextDataQuery::default()),
-            "character_system_text" => Box::new(sql::CharacterSystemTextQuery::default()),
-            "race_jikkyo_comment" => Box::new(sql::RaceJikkyoCommentQuery::default()),
-            "race_jikkyo_message" => Box::new(sql::RaceJikkyoMessageQuery::default()),
-            _ => return
-        };
+fn build_query_state(sql_str: &str) -> Option<BoxedQueryState> {
+    // parse the sql string
+    let dialect = SQLiteDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql_str).ok()?;
 
-        // Add columns
-        let mut i = 0;
-        for item in select.projection.iter() {
-            if let Some(name) = item.get_unnamed_expr_ident() {
-                query_state.add_column(i, name);
-                i += 1;
-            }
+    // only care about select statements
+    if !parser.parse_keyword(Keyword::SELECT) {
+        return None;
+    }
+    let select = parser.parse_select().ok()?;
+
+    // and their first table name (SELECT FROM table_name)
+    let Some(table_name) = select.get_first_table_name() else {
+        debug!("no table name");
+        return None;
+    };
+
+    // Create the query state
+    // Builtin tables always use their own handlers, config tables that shadow them are dropped on load
+    let mut query_state: BoxedQueryState = match table_name.as_ref() {
+        "text_data" => Box::new(sql::TextDataQuery::default()),
+        "character_system_text" => Box::new(sql::CharacterSystemTextQuery::default()),
+        "race_jikkyo_comment" => Box::new(sql::RaceJikkyoCommentQuery::default()),
+        "race_jikkyo_message" => Box::new(sql::RaceJikkyoMessageQuery::default()),
+        _ => {
+            let localized_data = Hachimi::instance().localized_data.load();
+            let config = localized_data.config.sql_tables.iter().find(|t| t.table == *table_name)?;
+            Box::new(sql::ConfigTableQuery::new(config))
         }
-
-        // Add params
-        i = 1; // index starts at 1
-        if let Some(selection) = select.selection {
-            // this should visit them in order (column1 = ? AND column2 = ? ...)
-            for expr in selection.binary_op_iter() {
-                if *expr.op != BinaryOperator::Eq { continue; }
-
-                if let Some(name) = expr.left.get_ident_value() {
-                    if expr.right.is_placeholder_value() {
-                        query_state.add_param(i, name);
-                        i += 1;
-                    }
-                }
-            }
+    };
+
+    // Add columns
+    let mut i = 0;
+    for item in select.projection.iter() {
+        if let Some(name) = item.get_unnamed_expr_ident() {
+            query_state.add_column(i, name);
+            i += 1;
         }
+    }
 
-        // Add query state
-        SELECT_QUERIES.lock().unwrap().insert(query as usize, query_state);
+    // Add params and literals
+    if let Some(selection) = &select.selection {
+        sql::add_where_clause(query_state.as_mut(), selection);
     }
+
+    Some(query_state)
 }
 
 type QueryFn = extern "C" fn(this: *mut Il2CppObject, sql: *const Il2CppString) -> *mut Il2CppObject;
@@ -1,15 +1,31 @@
-use crate::il2cpp::{symbols::get_method_addr, types::*};
+use crate::{core::Hachimi, il2cpp::{ext::Il2CppStringExt, symbols::get_method_addr, types::*}};
 
 use super::Connection::SELECT_QUERIES;
 
 type GetTextFn = extern "C" fn(this: *mut Il2CppObject, idx: i32) -> *mut Il2CppString;
 extern "C" fn GetText(this: *mut Il2CppObject, idx: i32) -> *mut Il2CppString {
     if let Some(query) = SELECT_QUERIES.lock().unwrap().get(&(this as usize)) {
-        return query.get_text(this, idx).unwrap_or_else(|| get_orig_fn!(GetText, GetTextFn)(this, idx));
+        if let Some(text) = query.get_text(this, idx) {
+            return text;
+        }
+
+        let text = get_orig_fn!(GetText, GetTextFn)(this, idx);
+        let hachimi = Hachimi::instance();
+        if hachimi.config.load().translator_mode && !text.is_null() {
+            if let Some((table, keys)) = query.dict_keys(this, idx) {
+                hachimi.sql_capture.record(table, &keys, unsafe { (*text).as_utf16str() }.to_string());
+            }
+        }
+        return text;
     }
     get_orig_fn!(GetText, GetTextFn)(this, idx)
 }
 
+/// Original GetText, for reading key columns without going through the hook.
+pub fn GetText_orig(this: *mut Il2CppObject, idx: i32) -> *mut Il2CppString {
+    get_orig_fn!(GetText, GetTextFn)(this, idx)
+}
+
 type DisposeFn = extern "C" fn(this: *mut Il2CppObject);
 extern "C" fn Dispose(this: *mut Il2CppObject) {
     SELECT_QUERIES.lock().unwrap().remove(&(this as usize));
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 use fnv::FnvHashMap;
 use serde::Deserialize;
@@ -77,7 +77,7 @@ pub fn on_LoadAsset(bundle: *mut Il2CppObject, this: *mut Il2CppObject, name: &U
     let base_path = name[AssetBundle::ASSET_PATH_PREFIX.len()..].path_basename();
 
     let localized_data = Hachimi::instance().localized_data.load();
-    let asset_info: AssetInfo<AnRootData> = localized_data.load_asset_info(&base_path.to_string());
+    let asset_info: Arc<AssetInfo<AnRootData>> = localized_data.load_asset_info(&base_path.to_string());
     if !AssetBundle::check_asset_bundle_name(bundle, asset_info.metadata_ref()) {
         return;
     }
@@ -28,7 +28,7 @@ extern "C" fn _UpdateText(this: *mut Il2CppObject) {
     // doesn't run through TextGenerator, ignore its filters
     if text.as_slice().contains(&36) { // 36 = dollar sign ($)
         set__text(this, Hachimi::instance().template_parser
-            .eval_with_context(&text.to_string(), &mut IgnoreTGFiltersContext())
+            .eval_with_context_escaped(&text.to_string(), &mut IgnoreTGFiltersContext())
             .to_il2cpp_string());
     }
     
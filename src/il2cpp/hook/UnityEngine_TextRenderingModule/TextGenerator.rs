@@ -1,6 +1,6 @@
 use std::ops::Not;
 
-use crate::{core::{template, Hachimi}, il2cpp::{ext::{Il2CppStringExt, StringExt}, symbols::get_method_addr, types::*}};
+use crate::{core::{coverage::CoverageSource, template, Hachimi}, il2cpp::{ext::{Il2CppStringExt, StringExt}, symbols::get_method_addr, types::*}};
 
 type PopulateWithErrorsFn = extern "C" fn(
     this: *mut Il2CppObject, str: *mut Il2CppString,
@@ -11,7 +11,8 @@ extern "C" fn PopulateWithErrors(
     mut settings: TextGenerationSettings_t, context: *mut Il2CppObject
 ) -> bool {
     let orig_fn = get_orig_fn!(PopulateWithErrors, PopulateWithErrorsFn);
-    let localized_data = &Hachimi::instance().localized_data.load();
+    let hachimi = Hachimi::instance();
+    let localized_data = &hachimi.localized_data.load();
     let hashed_dict = &localized_data.hashed_dict;
 
     let mut new_str: Option<&String> = None;
@@ -20,7 +21,15 @@ extern "C" fn PopulateWithErrors(
 
     // Check if the hashed dict has a match.
     let hashed_text = hashed_dict.is_empty().not()
-        .then(|| hashed_dict.get(&unsafe { (*str_).hash() }))
+        .then(|| {
+            let hash = unsafe { (*str_).hash() };
+            // Verified entries only match if their source text is the same
+            let text = hashed_dict.get(&hash).and_then(|e| e.get(unsafe { (*str_).as_utf16str() }));
+            hachimi.coverage.record_hashed(hash, text.is_some(), || unsafe {
+                (*str_).as_utf16str().to_string()
+            });
+            text
+        })
         .flatten();
     if let Some(text) = hashed_text {
         new_str = hashed_text;
@@ -73,8 +82,7 @@ impl<'a> template::Context for TemplateContext<'a> {
                 // 4  5  6
                 // 7  8  9
                 // Example: $(anchor 6) = middle right
-                let value = args.get(0)?;
-                let template::Token::NumberLit(anchor_num) = *value else {
+                let Some(anchor_num) = args.get(0)?.as_number() else {
                     return None;
                 };
                 let anchor = (anchor_num as i32) - 1;
@@ -86,8 +94,7 @@ impl<'a> template::Context for TemplateContext<'a> {
 
             "scale" => {
                 // Example: $(scale 80) = scale font size to 80%
-                let value = args.get(0)?;
-                let template::Token::NumberLit(percentage) = value else {
+                let Some(percentage) = args.get(0)?.as_number() else {
                     return None;
                 };
                 self.settings.fontSize = (self.settings.fontSize as f64 * (percentage / 100.0)) as i32;
@@ -95,8 +102,7 @@ impl<'a> template::Context for TemplateContext<'a> {
 
             "ho" => {
                 // $(ho 0) or $(ho 1)
-                let value = args.get(0)?;
-                let template::Token::NumberLit(overflow_num) = *value else {
+                let Some(overflow_num) = args.get(0)?.as_number() else {
                     return None;
                 };
                 let overflow = overflow_num as i32;
@@ -108,8 +114,7 @@ impl<'a> template::Context for TemplateContext<'a> {
 
             "vo" => {
                 // $(vo 0) or $(vo 1)
-                let value = args.get(0)?;
-                let template::Token::NumberLit(overflow_num) = *value else {
+                let Some(overflow_num) = args.get(0)?.as_number() else {
                     return None;
                 };
                 let overflow = overflow_num as i32;
@@ -120,8 +125,7 @@ impl<'a> template::Context for TemplateContext<'a> {
             }
 
             "ls" => {
-                let value = args.get(0)?;
-                let template::Token::NumberLit(ls) = *value else {
+                let Some(ls) = args.get(0)?.as_number() else {
                     return None;
                 };
                 self.settings.lineSpacing = ls as f32;
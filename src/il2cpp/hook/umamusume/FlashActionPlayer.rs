@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use serde::Deserialize;
 use widestring::Utf16Str;
 
@@ -35,7 +37,7 @@ pub fn on_LoadAsset(bundle: *mut Il2CppObject, this: *mut Il2CppObject, name: &U
     let base_path = name[AssetBundle::ASSET_PATH_PREFIX.len()..].path_basename();
 
     let localized_data = Hachimi::instance().localized_data.load();
-    let asset_info: AssetInfo<FlashActionPlayerData> = localized_data.load_asset_info(&base_path.to_string());
+    let asset_info: Arc<AssetInfo<FlashActionPlayerData>> = localized_data.load_asset_info(&base_path.to_string());
     if !AssetBundle::check_asset_bundle_name(bundle, asset_info.metadata_ref()) {
         return;
     }
@@ -47,7 +49,7 @@ pub fn on_LoadAsset(bundle: *mut Il2CppObject, this: *mut Il2CppObject, name: &U
 
     let root = GameObject::GetComponentInChildren(flash_prefab, AnRoot::type_object(), false);
     if !root.is_null() {
-        AnRoot::patch_asset(root, asset_info.data.map(|d| d.an_root).unwrap_or_default().as_ref());
+        AnRoot::patch_asset(root, asset_info.data.as_ref().and_then(|d| d.an_root.as_ref()));
     }
 }
 
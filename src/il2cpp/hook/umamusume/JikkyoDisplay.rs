@@ -16,7 +16,7 @@ extern "C" fn Play(
     let text = unsafe { (*jikkyou_text).as_utf16str() };
 
     if text.as_slice().contains(&36) { // 36 = dollar sign ($)
-        let processed = Hachimi::instance().template_parser.eval(&text.to_string());
+        let processed = Hachimi::instance().template_parser.eval_escaped(&text.to_string());
         let final_text = if let Some(wrapped) = wrap_text(&processed, LINE_WIDTH) {
             wrapped.join("\n")
         }
@@ -4,7 +4,7 @@ use fnv::FnvHashMap;
 use once_cell::unsync::Lazy;
 
 use crate::{
-    core::{utils, Hachimi, SugoiClient},
+    core::{coverage::CoverageSource, utils, Hachimi, SugoiClient},
     il2cpp::{ext::{Il2CppStringExt, StringExt}, symbols::{get_method_overload_addr, unbox}, types::*}
 };
 
@@ -38,7 +38,9 @@ pub extern "C" fn Get(id: i32) -> *mut Il2CppString {
         },
     };
 
-    if let Some(text) = localized_data.localize_dict.get(name) {
+    let text_opt = localized_data.localize_dict.get(name);
+    hachimi.coverage.record(CoverageSource::Localize, name, text_opt.is_some());
+    if let Some(text) = text_opt {
         text.to_il2cpp_string()
     }
     else {
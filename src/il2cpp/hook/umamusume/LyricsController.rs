@@ -1,9 +1,9 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 use fnv::FnvHashMap;
 
 use crate::{
-    core::{ext::Utf16StringExt, Hachimi, game::Region},
+    core::{coverage::CoverageSource, ext::Utf16StringExt, Hachimi, game::Region},
     il2cpp::{
         ext::{Il2CppStringExt, StringExt},
         symbols::{get_field_from_name, get_field_object_value, get_method_addr, Array, Dictionary},
@@ -68,13 +68,16 @@ extern "C" fn LoadLyrics(this: *mut Il2CppObject, id: i32, path: *mut Il2CppStri
 
     let mut dict_path = Path::new("lyrics").join(path_str.path_filename().to_string());
     dict_path.set_extension("json");
-    let localized_data = Hachimi::instance().localized_data.load();
-    let Some(dict): Option<FnvHashMap<i32, String>> = localized_data.load_assets_dict(Some(&dict_path)) else {
+    let hachimi = Hachimi::instance();
+    let localized_data = hachimi.localized_data.load();
+    let dict_opt: Option<Arc<FnvHashMap<i32, String>>> = localized_data.load_assets_dict(Some(&dict_path));
+    hachimi.coverage.record(CoverageSource::Lyrics, dict_path.display(), dict_opt.is_some());
+    let Some(dict) = dict_opt else {
         return true;
     };
     // dont let pbork interactive know about this
-    let secs_dict: FnvHashMap<i32, String> = dict.into_iter()
-        .map(|(time, lyrics)| (f32::to_bits(time as f32 / 1000.0).cast_signed(), lyrics) )
+    let secs_dict: FnvHashMap<i32, &String> = dict.iter()
+        .map(|(time, lyrics)| (f32::to_bits(*time as f32 / 1000.0).cast_signed(), lyrics) )
         .collect();
 
     let lyrics_data_dict = get__lyricsDataDic(this);
@@ -31,7 +31,7 @@ extern "C" fn GetMonthTextByTurn(turn_set_id: i32, turn: i32) -> *mut Il2CppStri
 
         let turn = GetMasterTurn(turn_set_id, turn);
         return Hachimi::instance().template_parser
-            .eval_with_context(format, &mut Context { turn })
+            .eval_with_context_escaped(format, &mut Context { turn })
             .to_il2cpp_string()
     }
 
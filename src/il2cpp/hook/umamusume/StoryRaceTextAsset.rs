@@ -1,9 +1,9 @@
-use std::ptr::null_mut;
+use std::{ptr::null_mut, sync::Arc};
 
 use widestring::Utf16Str;
 
 use crate::{
-    core::{ext::Utf16StringExt, Hachimi},
+    core::{coverage::CoverageSource, ext::Utf16StringExt, Hachimi},
     il2cpp::{
         ext::{Il2CppObjectExt, StringExt}, hook::UnityEngine_AssetBundleModule::AssetBundle::ASSET_PATH_PREFIX, symbols::{get_field_from_name, get_field_object_value, set_field_object_value, Array}, types::*
     }
@@ -40,8 +40,11 @@ pub fn on_LoadAsset(_bundle: *mut Il2CppObject, this: *mut Il2CppObject, name: &
 
     let base_path = name[ASSET_PATH_PREFIX.len()..].path_basename();
     let dict_path = base_path.to_string() + ".json";
-    let localized_data = Hachimi::instance().localized_data.load();
-    let Some(dict): Option<Vec<String>> = localized_data.load_assets_dict(Some(&dict_path)) else {
+    let hachimi = Hachimi::instance();
+    let localized_data = hachimi.localized_data.load();
+    let dict_opt: Option<Arc<Vec<String>>> = localized_data.load_assets_dict(Some(&dict_path));
+    hachimi.coverage.record(CoverageSource::RaceStory, &dict_path, dict_opt.is_some());
+    let Some(dict) = dict_opt else {
         return;
     };
 
@@ -1,11 +1,11 @@
-use std::ptr::null_mut;
+use std::{ptr::null_mut, sync::Arc};
 
 use fnv::FnvHashMap;
 use serde::{Deserialize, Serialize};
 use widestring::Utf16Str;
 
 use crate::{
-    core::{ext::Utf16StringExt, utils, Error, Hachimi, SugoiClient}, 
+    core::{coverage::CoverageSource, ext::Utf16StringExt, utils, Error, Hachimi, SugoiClient}, 
     il2cpp::{
         ext::{Il2CppStringExt, StringExt}, hook::{umamusume::{StoryTimelineCharaTrackData, StoryTimelineClipData}, UnityEngine_AssetBundleModule::AssetBundle::ASSET_PATH_PREFIX}, symbols::{get_field_from_name, get_field_object_value, get_field_value, set_field_object_value, set_field_value, IList}, types::*
     }
@@ -61,35 +61,35 @@ fn set_Length(this: *mut Il2CppObject, value: i32) {
 
 // (Aliases are there for tlg compatibility)
 #[derive(Serialize, Deserialize, Default)]
-struct StoryTimelineDataDict {
+pub struct StoryTimelineDataDict {
     #[serde(alias = "Title")]
-    title: Option<String>,
+    pub title: Option<String>,
 
     #[serde(alias = "TextBlockList")]
     #[serde(default)]
-    text_block_list: Vec<TextBlockDict>,
+    pub text_block_list: Vec<TextBlockDict>,
 
     #[serde(default)]
-    no_wrap: bool
+    pub no_wrap: bool
 }
 
 #[derive(Serialize, Deserialize, Default)]
-struct TextBlockDict {
+pub struct TextBlockDict {
     #[serde(alias = "Name")]
-    name: Option<String>,
+    pub name: Option<String>,
 
     #[serde(alias = "Text")]
-    text: Option<String>,
+    pub text: Option<String>,
 
     #[serde(alias = "ChoiceDataList")]
     #[serde(default)]
-    choice_data_list: Vec<String>,
+    pub choice_data_list: Vec<String>,
 
     #[serde(alias = "ColorTextInfoList")]
     #[serde(default)]
-    color_text_info_list: Vec<String>,
+    pub color_text_info_list: Vec<String>,
 
-    new_clip_length: Option<i32>
+    pub new_clip_length: Option<i32>
 }
 
 // hook::UnityEngine_AssetBundleModule::AssetBundle
@@ -114,7 +114,9 @@ pub fn on_LoadAsset(_bundle: *mut Il2CppObject, this: *mut Il2CppObject, name: &
     let dict_path = base_path.to_string() + ".json";
 
     let localized_data = hachimi.localized_data.load();
-    let Some(dict): Option<StoryTimelineDataDict> = localized_data.load_assets_dict(Some(&dict_path)).or_else(|| {
+    let dict_opt: Option<Arc<StoryTimelineDataDict>> = localized_data.load_assets_dict(Some(&dict_path));
+    hachimi.coverage.record(CoverageSource::Story, &dict_path, dict_opt.is_some());
+    let Some(dict) = dict_opt.or_else(|| {
         if hachimi.config.load().auto_translate_stories {
             let Some(full_dict_path) = localized_data.get_assets_path(&dict_path) else {
                 return None;
@@ -135,7 +137,7 @@ pub fn on_LoadAsset(_bundle: *mut Il2CppObject, this: *mut Il2CppObject, name: &
                             error!("Failed to save auto TL dict: {}", e);
                         }
                     }
-                    Some(dict)
+                    Some(Arc::new(dict))
                 },
                 Err(e) => {
                     error!("Failed to auto translate: {}", e);
@@ -9,7 +9,7 @@ fn PlayTypeWriteCommon(mut message: *mut Il2CppString) -> *mut Il2CppString {
     let message_utf16 = unsafe { (*message).as_utf16str() };
     if message_utf16.as_slice().contains(&36) { // 36 = dollar sign ($)
         message = Hachimi::instance().template_parser
-            .eval_with_context(&message_utf16.to_string(), &mut IgnoreTGFiltersContext())
+            .eval_with_context_escaped(&message_utf16.to_string(), &mut IgnoreTGFiltersContext())
             .to_il2cpp_string()
     }
     message
@@ -3,45 +3,103 @@ use std::{ptr, sync::atomic::{self, AtomicPtr}};
 use sqlparser::ast;
 
 use crate::{
-    core::{utils, Hachimi},
-    il2cpp::{ext::StringExt, hook::LibNative_Runtime, types::{Il2CppObject, Il2CppString}}
+    core::{coverage::CoverageSource, sql_table::SqlTableConfig, utils, Hachimi},
+    il2cpp::{ext::{Il2CppStringExt, StringExt}, hook::LibNative_Runtime, types::{Il2CppObject, Il2CppString}}
 };
 
 // All of this add column/param stuff could be simplified to two hash maps, but that's overkill.
-pub trait SelectQueryState {
+pub trait SelectQueryState: SelectQueryStateClone {
     /// Adds a column to the query.
     /// 
     /// Implementers are expected to only track the index of columns that they need.
     fn add_column(&mut self, idx: i32, name: &str);
 
     /// Adds a placeholder parameter to the query (WHERE param = ?).
+    /// Called once for each placeholder of an IN list.
     /// 
     /// Index starts at 1.
     fn add_param(&mut self, idx: i32, name: &str);
 
-    /// Bind an int value to a placeholder.
+    /// Adds a literal value to the query (WHERE param = 47).
+    /// Called once for each value of an IN list.
+    fn add_literal(&mut self, name: &str, value: SqlValue);
+
+    /// Bind a value to a placeholder.
     /// 
     /// Index starts at 1.
-    fn bind_int(&mut self, idx: i32, value: i32);
+    fn bind_value(&mut self, idx: i32, value: &SqlValue);
 
     /// Gets the resulting string on the current row's column.
     fn get_text(&self, query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString>;
+
+    /// Gets the table name and dict keys of the current row, if the column is the translated text column.
+    /// 
+    /// Used to capture untranslated text in translator mode.
+    fn dict_keys(&self, query: *mut Il2CppObject, idx: i32) -> Option<(&str, Vec<String>)>;
 }
 
-#[derive(Default)]
+/// Allows parsed query states to be cached and cloned for each new query.
+pub trait SelectQueryStateClone {
+    fn clone_box(&self) -> Box<dyn SelectQueryState + Send + Sync>;
+}
+
+impl<T: SelectQueryState + Clone + Send + Sync + 'static> SelectQueryStateClone for T {
+    fn clone_box(&self) -> Box<dyn SelectQueryState + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// Value of a key column, as bound to a placeholder or written in the query.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SqlValue {
+    Int(i64),
+    Text(String)
+}
+
+impl SqlValue {
+    pub fn from_expr(expr: &ast::Expr) -> Option<Self> {
+        match expr {
+            ast::Expr::Value(ast::Value::Number(n, _)) => n.parse().ok().map(SqlValue::Int),
+            ast::Expr::Value(ast::Value::SingleQuotedString(s)) => Some(SqlValue::Text(s.clone())),
+            ast::Expr::UnaryOp { op: ast::UnaryOperator::Minus, expr } => match Self::from_expr(expr)? {
+                SqlValue::Int(n) => Some(SqlValue::Int(-n)),
+                SqlValue::Text(_) => None
+            },
+            ast::Expr::Nested(expr) => Self::from_expr(expr),
+            _ => None
+        }
+    }
+
+    fn as_i32(&self) -> Option<i32> {
+        match self {
+            SqlValue::Int(n) => i32::try_from(*n).ok(),
+            SqlValue::Text(s) => s.parse().ok()
+        }
+    }
+
+    fn to_key(&self) -> String {
+        match self {
+            SqlValue::Int(n) => n.to_string(),
+            SqlValue::Text(s) => s.clone()
+        }
+    }
+}
+
+#[derive(Default, Clone)]
 struct Column {
     /// Index of the column in the SELECT statement.
     /// 
     /// Can be used to query the value later if needed.
     select_idx: Option<i32>,
 
-    /// Index of the placeholder param for this column.
+    /// Indices of the placeholder params for this column (more than one for IN lists),
+    /// along with the values binded to them.
     /// 
     /// If this column's value is already binded as a param in the query, we won't need to query it later.
-    param_idx: Option<i32>,
+    params: Vec<(i32, Option<SqlValue>)>,
 
-    /// The int value binded to this column as a parameter.
-    int_value: Option<i32>
+    /// Literal values for this column in the query.
+    literals: Vec<SqlValue>
 }
 
 impl Column {
@@ -54,19 +112,38 @@ impl Column {
         }
     }
 
-    fn is_param_idx(&self, idx: i32) -> bool {
-        if let Some(i) = self.param_idx {
-            idx == i
-        }
-        else {
-            false
+    fn add_param(&mut self, idx: i32) {
+        self.params.push((idx, None));
+    }
+
+    fn add_literal(&mut self, value: SqlValue) {
+        self.literals.push(value);
+    }
+
+    fn try_bind(&mut self, idx: i32, value: &SqlValue) {
+        for (param_idx, param_value) in self.params.iter_mut() {
+            if *param_idx == idx {
+                *param_value = Some(value.clone());
+            }
         }
     }
 
-    fn try_bind_int(&mut self, idx: i32, value: i32) {
-        if self.is_param_idx(idx) {
-            self.int_value = Some(value);
+    /// The value of this column if the query restricts it to a single one.
+    /// 
+    /// IN lists with multiple values need the value to be queried from the current row instead.
+    fn known_value(&self) -> Option<&SqlValue> {
+        let mut values: Vec<&SqlValue> = self.literals.iter().collect();
+        for (_, value) in self.params.iter() {
+            // Not binded yet
+            values.push(value.as_ref()?);
         }
+
+        let first = *values.first()?;
+        values.iter().all(|v| *v == first).then_some(first)
+    }
+
+    fn int_value(&self) -> Option<i32> {
+        self.known_value()?.as_i32()
     }
 
     fn try_get_int(&self, query: *mut Il2CppObject) -> Option<i32> {
@@ -79,7 +156,7 @@ impl Column {
     }
 
     fn value_or_try_get_int(&self, query: *mut Il2CppObject) -> Option<i32> {
-        if let Some(value) = self.int_value {
+        if let Some(value) = self.int_value() {
             Some(value)
         }
         else if let Some(value) = self.try_get_int(query) {
@@ -89,15 +166,29 @@ impl Column {
             None
         }
     }
+
+    /// Value of the column as a dict key, queried as text from the current row if needed.
+    fn value_or_try_get_key(&self, query: *mut Il2CppObject) -> Option<String> {
+        if let Some(value) = self.known_value() {
+            return Some(value.to_key());
+        }
+
+        let idx = self.select_idx?;
+        let text = LibNative_Runtime::Sqlite3::Query::GetText_orig(query, idx);
+        if text.is_null() {
+            return None;
+        }
+        Some(unsafe { (*text).as_utf16str() }.to_string())
+    }
 }
 
 // text_data
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TextDataQuery {
     // SELECT
     text: Column,
 
-    // WHERE
+    // may appear in both
     category: Column,
     index: Column
 }
@@ -197,60 +288,86 @@ impl TextDataQuery {
 
 impl SelectQueryState for TextDataQuery {
     fn add_column(&mut self, idx: i32, name: &str) {
-        if name == "text" {
-            self.text.select_idx = Some(idx)
+        match name {
+            "text" => self.text.select_idx = Some(idx),
+            "category" => self.category.select_idx = Some(idx),
+            "index" => self.index.select_idx = Some(idx),
+            _ => ()
         }
     }
 
     fn add_param(&mut self, idx: i32, name: &str) {
         match name {
-            "category" => self.category.param_idx = Some(idx),
-            "index" => self.index.param_idx = Some(idx),
+            "category" => self.category.add_param(idx),
+            "index" => self.index.add_param(idx),
             _ => ()
         }
     }
 
-    fn bind_int(&mut self, idx: i32, value: i32) {
-        self.category.try_bind_int(idx, value);
-        self.index.try_bind_int(idx, value);
+    fn add_literal(&mut self, name: &str, value: SqlValue) {
+        match name {
+            "category" => self.category.add_literal(value),
+            "index" => self.index.add_literal(value),
+            _ => ()
+        }
+    }
+
+    fn bind_value(&mut self, idx: i32, value: &SqlValue) {
+        self.category.try_bind(idx, value);
+        self.index.try_bind(idx, value);
     }
 
-    fn get_text(&self, _query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString> {
+    fn get_text(&self, query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString> {
         if !self.text.is_select_idx(idx) {
             return None;
         }
 
-        if let Some(category) = self.category.int_value {
-            if let Some(index) = self.index.int_value {
+        if let Some(category) = self.category.value_or_try_get_int(query) {
+            if let Some(index) = self.index.value_or_try_get_int(query) {
                 // specialized handlers
-                match category {
-                    47 => return Self::get_skill_name(index),
-                    48 => return Self::get_skill_desc(index),
-                    _ => ()
+                let text = match category {
+                    47 => Self::get_skill_name(index),
+                    48 => Self::get_skill_desc(index),
+                    _ => Hachimi::instance().localized_data.load()
+                        .text_data_dict
+                        .get(&category)
+                        .map(|c| c.get(&index).map(|s| s.to_il2cpp_string()))
+                        .unwrap_or_default()
                 };
 
-                return Hachimi::instance().localized_data.load()
-                    .text_data_dict
-                    .get(&category)
-                    .map(|c| c.get(&index).map(|s| s.to_il2cpp_string()))
-                    .unwrap_or_default()
+                Hachimi::instance().coverage.record(
+                    CoverageSource::TextData, format_args!("{}/{}", category, index), text.is_some()
+                );
+                return text;
             }
         }
 
         None
     }
+
+    fn dict_keys(&self, query: *mut Il2CppObject, idx: i32) -> Option<(&str, Vec<String>)> {
+        if !self.text.is_select_idx(idx) {
+            return None;
+        }
+
+        let category = self.category.value_or_try_get_int(query)?;
+        // Skill names might be translated but left untouched on purpose
+        if category == 47 && Hachimi::instance().config.load().disable_skill_name_translation {
+            return None;
+        }
+        let index = self.index.value_or_try_get_int(query)?;
+        Some(("text_data", vec![category.to_string(), index.to_string()]))
+    }
 }
 
 // character_system_text
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CharacterSystemTextQuery {
     // SELECT
     text: Column,
 
-    // WHERE
-    character_id: Column,
-
     // may appear in both
+    character_id: Column,
     voice_id: Column
 }
 
@@ -258,6 +375,7 @@ impl SelectQueryState for CharacterSystemTextQuery {
     fn add_column(&mut self, idx: i32, name: &str) {
         match name {
             "text" => self.text.select_idx = Some(idx),
+            "character_id" => self.character_id.select_idx = Some(idx),
             "voice_id" => self.voice_id.select_idx = Some(idx),
             _ => ()
         }
@@ -265,15 +383,23 @@ impl SelectQueryState for CharacterSystemTextQuery {
 
     fn add_param(&mut self, idx: i32, name: &str) {
         match name {
-            "character_id" => self.character_id.param_idx = Some(idx),
-            "voice_id" => self.voice_id.param_idx = Some(idx),
+            "character_id" => self.character_id.add_param(idx),
+            "voice_id" => self.voice_id.add_param(idx),
             _ => ()
         }
     }
 
-    fn bind_int(&mut self, idx: i32, value: i32) {
-        self.character_id.try_bind_int(idx, value);
-        self.voice_id.try_bind_int(idx, value);
+    fn add_literal(&mut self, name: &str, value: SqlValue) {
+        match name {
+            "character_id" => self.character_id.add_literal(value),
+            "voice_id" => self.voice_id.add_literal(value),
+            _ => ()
+        }
+    }
+
+    fn bind_value(&mut self, idx: i32, value: &SqlValue) {
+        self.character_id.try_bind(idx, value);
+        self.voice_id.try_bind(idx, value);
     }
 
     fn get_text(&self, query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString> {
@@ -281,22 +407,37 @@ impl SelectQueryState for CharacterSystemTextQuery {
             return None;
         }
 
-        if let Some(character_id) = self.character_id.int_value {
+        if let Some(character_id) = self.character_id.value_or_try_get_int(query) {
             if let Some(voice_id) = self.voice_id.value_or_try_get_int(query) {
-                return Hachimi::instance().localized_data.load()
+                let text = Hachimi::instance().localized_data.load()
                     .character_system_text_dict
                     .get(&character_id)
                     .map(|c| c.get(&voice_id).map(|s| s.to_il2cpp_string()))
-                    .unwrap_or_default()
+                    .unwrap_or_default();
+
+                Hachimi::instance().coverage.record(
+                    CoverageSource::CharacterSystemText, format_args!("{}/{}", character_id, voice_id), text.is_some()
+                );
+                return text;
             }
         }
 
         None
     }
+
+    fn dict_keys(&self, query: *mut Il2CppObject, idx: i32) -> Option<(&str, Vec<String>)> {
+        if !self.text.is_select_idx(idx) {
+            return None;
+        }
+
+        let character_id = self.character_id.value_or_try_get_int(query)?;
+        let voice_id = self.voice_id.value_or_try_get_int(query)?;
+        Some(("character_system_text", vec![character_id.to_string(), voice_id.to_string()]))
+    }
 }
 
 // race_jikkyo_comment
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct RaceJikkyoCommentQuery {
     // SELECT
     id: Column,
@@ -312,28 +453,52 @@ impl SelectQueryState for RaceJikkyoCommentQuery {
         }
     }
 
-    fn add_param(&mut self, _idx: i32, _name: &str) {}
+    fn add_param(&mut self, idx: i32, name: &str) {
+        if name == "id" {
+            self.id.add_param(idx);
+        }
+    }
+
+    fn add_literal(&mut self, name: &str, value: SqlValue) {
+        if name == "id" {
+            self.id.add_literal(value);
+        }
+    }
 
-    fn bind_int(&mut self, _idx: i32, _value: i32) {}
+    fn bind_value(&mut self, idx: i32, value: &SqlValue) {
+        self.id.try_bind(idx, value);
+    }
 
     fn get_text(&self, query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString> {
         if !self.message.is_select_idx(idx) {
             return None;
         }
 
-        if let Some(id) = self.id.try_get_int(query) {
-            return Hachimi::instance().localized_data.load()
+        if let Some(id) = self.id.value_or_try_get_int(query) {
+            let text = Hachimi::instance().localized_data.load()
                 .race_jikkyo_comment_dict
                 .get(&id)
-                .map(|s| s.to_il2cpp_string())
+                .map(|s| s.to_il2cpp_string());
+
+            Hachimi::instance().coverage.record(CoverageSource::RaceJikkyoComment, id, text.is_some());
+            return text;
         }
 
         None
     }
+
+    fn dict_keys(&self, query: *mut Il2CppObject, idx: i32) -> Option<(&str, Vec<String>)> {
+        if !self.message.is_select_idx(idx) {
+            return None;
+        }
+
+        let id = self.id.value_or_try_get_int(query)?;
+        Some(("race_jikkyo_comment", vec![id.to_string()]))
+    }
 }
 
 // race_jikkyo_message
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct RaceJikkyoMessageQuery {
     // SELECT
     id: Column,
@@ -349,24 +514,142 @@ impl SelectQueryState for RaceJikkyoMessageQuery {
         }
     }
 
-    fn add_param(&mut self, _idx: i32, _name: &str) {}
+    fn add_param(&mut self, idx: i32, name: &str) {
+        if name == "id" {
+            self.id.add_param(idx);
+        }
+    }
+
+    fn add_literal(&mut self, name: &str, value: SqlValue) {
+        if name == "id" {
+            self.id.add_literal(value);
+        }
+    }
 
-    fn bind_int(&mut self, _idx: i32, _value: i32) {}
+    fn bind_value(&mut self, idx: i32, value: &SqlValue) {
+        self.id.try_bind(idx, value);
+    }
 
     fn get_text(&self, query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString> {
         if !self.message.is_select_idx(idx) {
             return None;
         }
 
-        if let Some(id) = self.id.try_get_int(query) {
-            return Hachimi::instance().localized_data.load()
+        if let Some(id) = self.id.value_or_try_get_int(query) {
+            let text = Hachimi::instance().localized_data.load()
                 .race_jikkyo_message_dict
                 .get(&id)
-                .map(|s| s.to_il2cpp_string())
+                .map(|s| s.to_il2cpp_string());
+
+            Hachimi::instance().coverage.record(CoverageSource::RaceJikkyoMessage, id, text.is_some());
+            return text;
         }
 
         None
     }
+
+    fn dict_keys(&self, query: *mut Il2CppObject, idx: i32) -> Option<(&str, Vec<String>)> {
+        if !self.message.is_select_idx(idx) {
+            return None;
+        }
+
+        let id = self.id.value_or_try_get_int(query)?;
+        Some(("race_jikkyo_message", vec![id.to_string()]))
+    }
+}
+
+
+// Tables declared in the localized data config
+#[derive(Clone)]
+pub struct ConfigTableQuery {
+    table: String,
+
+    // SELECT
+    text: Column,
+    text_column: String,
+
+    // WHERE or SELECT, in the same order as the dict's nesting
+    keys: Vec<(String, Column)>
+}
+
+impl ConfigTableQuery {
+    pub fn new(config: &SqlTableConfig) -> Self {
+        ConfigTableQuery {
+            table: config.table.clone(),
+            text: Column::default(),
+            text_column: config.text_column.clone(),
+            keys: config.key_columns.iter().map(|name| (name.clone(), Column::default())).collect()
+        }
+    }
+}
+
+impl SelectQueryState for ConfigTableQuery {
+    fn add_column(&mut self, idx: i32, name: &str) {
+        if name == self.text_column {
+            self.text.select_idx = Some(idx);
+        }
+        for (key_name, column) in self.keys.iter_mut() {
+            if *key_name == name {
+                column.select_idx = Some(idx);
+            }
+        }
+    }
+
+    fn add_param(&mut self, idx: i32, name: &str) {
+        for (key_name, column) in self.keys.iter_mut() {
+            if *key_name == name {
+                column.add_param(idx);
+            }
+        }
+    }
+
+    fn add_literal(&mut self, name: &str, value: SqlValue) {
+        for (key_name, column) in self.keys.iter_mut() {
+            if *key_name == name {
+                column.add_literal(value.clone());
+            }
+        }
+    }
+
+    fn bind_value(&mut self, idx: i32, value: &SqlValue) {
+        for (_, column) in self.keys.iter_mut() {
+            column.try_bind(idx, value);
+        }
+    }
+
+    fn get_text(&self, query: *mut Il2CppObject, idx: i32) -> Option<*mut Il2CppString> {
+        if !self.text.is_select_idx(idx) {
+            return None;
+        }
+
+        let mut keys = Vec::with_capacity(self.keys.len());
+        for (_, column) in self.keys.iter() {
+            keys.push(column.value_or_try_get_key(query)?);
+        }
+
+        let hachimi = Hachimi::instance();
+        let text = hachimi.localized_data.load()
+            .sql_table_dicts
+            .get(&self.table)
+            .and_then(|dict| dict.get(keys.as_slice()))
+            .map(|s| s.to_il2cpp_string());
+
+        hachimi.coverage.record(
+            CoverageSource::SqlTable, format_args!("{}/{}", self.table, keys.join("/")), text.is_some()
+        );
+        text
+    }
+
+    fn dict_keys(&self, query: *mut Il2CppObject, idx: i32) -> Option<(&str, Vec<String>)> {
+        if !self.text.is_select_idx(idx) {
+            return None;
+        }
+
+        let keys = self.keys.iter()
+            .map(|(_, column)| column.value_or_try_get_key(query))
+            .collect::<Option<Vec<_>>>()?;
+        Some((self.table.as_str(), keys))
+    }
 }
 
 
@@ -404,16 +687,12 @@ impl SelectItemExt for ast::SelectItem {
 }
 
 pub trait ExprExt {
-    fn binary_op_iter<'a>(&'a self) -> BinaryOpIter<'a>;
     fn get_ident_value(&self) -> Option<&String>;
-    fn is_placeholder_value(&self) -> bool;
+    fn get_column_name(&self) -> Option<&String>;
+    fn get_placeholder(&self) -> Option<&String>;
 }
 
 impl ExprExt for ast::Expr {
-    fn binary_op_iter<'a>(&'a self) -> BinaryOpIter<'a> {
-        BinaryOpIter { stack: vec![self] }
-    }
-
     fn get_ident_value(&self) -> Option<&String> {
         if let ast::Expr::Identifier(ident) = self {
             return Some(&ident.value);
@@ -422,44 +701,121 @@ impl ExprExt for ast::Expr {
         None
     }
 
-    fn is_placeholder_value(&self) -> bool {
-        if let ast::Expr::Value(value) = self {
-            if let ast::Value::Placeholder(_) = value {
-                return true;
-            }
+    /// Like get_ident_value, but also accepts qualified names (table.column).
+    fn get_column_name(&self) -> Option<&String> {
+        match self {
+            ast::Expr::Identifier(ident) => Some(&ident.value),
+            ast::Expr::CompoundIdentifier(idents) => idents.last().map(|i| &i.value),
+            _ => None
+        }
+    }
+
+    fn get_placeholder(&self) -> Option<&String> {
+        if let ast::Expr::Value(ast::Value::Placeholder(placeholder)) = self {
+            return Some(placeholder);
         }
 
-        false
+        None
     }
 }
 
-pub struct BinaryOpIter<'a> {
-    stack: Vec<&'a ast::Expr>
+// WHERE clause handling
+/// Adds the key values of a WHERE clause to the query state.
+/// 
+/// Handles `column = ?`, `column = literal` and `column IN (...)` conditions joined with AND.
+/// Placeholders anywhere else are only counted so that the param indices stay correct.
+pub fn add_where_clause(state: &mut dyn SelectQueryState, selection: &ast::Expr) {
+    let mut next_idx = 1; // index starts at 1
+    visit_where_expr(state, selection, &mut next_idx);
 }
 
-pub struct BinaryOpRef<'a> {
-    pub left: &'a Box<ast::Expr>,
-    pub op: &'a ast::BinaryOperator,
-    pub right: &'a Box<ast::Expr>
-}
+fn visit_where_expr(state: &mut dyn SelectQueryState, expr: &ast::Expr, next_idx: &mut i32) {
+    match expr {
+        ast::Expr::BinaryOp { left, op: ast::BinaryOperator::And, right } => {
+            visit_where_expr(state, left, next_idx);
+            visit_where_expr(state, right, next_idx);
+        }
+
+        ast::Expr::Nested(inner) => visit_where_expr(state, inner, next_idx),
 
-impl<'a> Iterator for BinaryOpIter<'a> {
-    type Item = BinaryOpRef<'a>;
+        ast::Expr::BinaryOp { left, op: ast::BinaryOperator::Eq, right } => {
+            // The column can be on either side
+            if let Some(name) = left.get_column_name() {
+                add_where_value(state, name, right, next_idx);
+            }
+            else if let Some(name) = right.get_column_name() {
+                add_where_value(state, name, left, next_idx);
+            }
+            else {
+                count_placeholders(expr, next_idx);
+            }
+        }
+
+        ast::Expr::InList { expr: inner, list, negated: false } => {
+            if let Some(name) = inner.get_column_name() {
+                for item in list {
+                    add_where_value(state, name, item, next_idx);
+                }
+            }
+            else {
+                count_placeholders(expr, next_idx);
+            }
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let Some(expr) = self.stack.pop() else {
-                return None;
-            };
+        _ => count_placeholders(expr, next_idx)
+    }
+}
 
-            let ast::Expr::BinaryOp { left, op, right } = expr else {
-                continue;
-            };
+fn add_where_value(state: &mut dyn SelectQueryState, name: &str, expr: &ast::Expr, next_idx: &mut i32) {
+    if let Some(placeholder) = expr.get_placeholder() {
+        state.add_param(placeholder_idx(placeholder, next_idx), name);
+    }
+    else if let Some(value) = SqlValue::from_expr(expr) {
+        state.add_literal(name, value);
+    }
+    else {
+        count_placeholders(expr, next_idx);
+    }
+}
 
-            self.stack.push(right);
-            self.stack.push(left); // left will be pop'd first
+/// Index of a placeholder, "?NNN" placeholders have an explicit index.
+fn placeholder_idx(placeholder: &str, next_idx: &mut i32) -> i32 {
+    let idx = placeholder.strip_prefix('?')
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(*next_idx);
+    *next_idx = (*next_idx).max(idx + 1);
+    idx
+}
 
-            return Some(BinaryOpRef { left, op, right })
+fn count_placeholders(expr: &ast::Expr, next_idx: &mut i32) {
+    match expr {
+        ast::Expr::Value(ast::Value::Placeholder(placeholder)) => {
+            placeholder_idx(placeholder, next_idx);
+        }
+        ast::Expr::BinaryOp { left, right, .. } => {
+            count_placeholders(left, next_idx);
+            count_placeholders(right, next_idx);
+        }
+        ast::Expr::UnaryOp { expr, .. } |
+        ast::Expr::Nested(expr) |
+        ast::Expr::IsNull(expr) |
+        ast::Expr::IsNotNull(expr) |
+        ast::Expr::Cast { expr, .. } => count_placeholders(expr, next_idx),
+        ast::Expr::InList { expr, list, .. } => {
+            count_placeholders(expr, next_idx);
+            for item in list {
+                count_placeholders(item, next_idx);
+            }
+        }
+        ast::Expr::Between { expr, low, high, .. } => {
+            count_placeholders(expr, next_idx);
+            count_placeholders(low, next_idx);
+            count_placeholders(high, next_idx);
         }
+        ast::Expr::Like { expr, pattern, .. } => {
+            count_placeholders(expr, next_idx);
+            count_placeholders(pattern, next_idx);
+        }
+        _ => ()
     }
-}
\ No newline at end of file
+}